@@ -1,189 +1,280 @@
-extern crate rayon;
-extern crate regex;
-extern crate serde;
-#[macro_use]
-extern crate serde_json;
-#[macro_use]
-extern crate serde_derive;
+extern crate city_climes_core;
+extern crate geonames;
 #[macro_use]
 extern crate lazy_static;
+extern crate rayon;
+extern crate regex;
 extern crate reqwest;
 extern crate scraper;
+#[macro_use]
+extern crate serde_json;
 
+use city_climes_core::fixtures::fixture_file;
+use city_climes_core::http::HttpClient;
+use city_climes_core::restbase::Wiki;
+use city_climes_core::{table_data, CountrySource, Location};
+use geonames::{parse_cities, parse_country_info, CountryInfo, GeonamesCity, NameIndex};
 use rayon::prelude::*;
 use regex::Regex;
-use reqwest::{Client, Response};
-use scraper::element_ref::ElementRef;
+use reqwest::blocking::Response;
 use scraper::{Html, Selector};
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
 lazy_static! {
-    static ref CLIENT: Client = Client::new();
-    static ref COUNTRY: Regex = Regex::new(r"(?i)country").unwrap();
+    static ref COUNTRY: Regex = Regex::new(r"(?i)^country$").unwrap();
 }
 
 pub struct Config {
-    pub json_file: String,
-    pub output_directory: String,
+    pub json_file: PathBuf,
+    /// Geonames cities file, e.g. `cities15000.txt`.
+    pub cities_file: PathBuf,
+    /// Geonames `countryInfo.txt` file.
+    pub country_info_file: PathBuf,
+    pub output_directory: PathBuf,
+    /// Scrape the infobox of the location's Wikipedia page when no geonames
+    /// city has its name.
+    pub scrape_fallback: bool,
+    /// Read pages from saved HTML files in this directory instead of
+    /// fetching them (see `city_climes_core::fixtures`).
+    pub fixtures_directory: Option<PathBuf>,
+    /// The client to fetch pages with.
+    pub client: HttpClient,
+    /// The Wikipedia to scrape infoboxes from.
+    pub wiki: Wiki,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Location {
-    ID: u32,
-    WikipediaURL: String,
-    LocationName: String,
-    AverageHighC: Vec<f64>,
-    AverageLowC: Vec<f64>,
-    AverageHighF: Vec<f64>,
-    AverageLowF: Vec<f64>,
-    SunshineHours: Option<Vec<f64>>,
-    Country: Option<String>,
-}
-
-impl Config {
-    pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
-        args.next();
-
-        let json_file = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a json file path."),
-        };
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let json_file = fs::read_to_string(&config.json_file).expect("Unable to read json file");
+    let cities_file = fs::read_to_string(&config.cities_file).expect("Unable to read cities file");
+    let country_info_file =
+        fs::read_to_string(&config.country_info_file).expect("Unable to read country info file");
 
-        let output_directory = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a output directory path."),
-        };
-
-        Ok(Config {
-            json_file,
-            output_directory,
-        })
-    }
-}
-
-pub fn run(config: Config) -> Result<(), Box<Error>> {
-    let json_file = fs::read_to_string(config.json_file).expect("Unable to read json file");
-
-    let out_file_path = format!("{}CityClimesCountries.json", config.output_directory);
+    let out_file_path = config.output_directory.join("CityClimesCountries.json");
     let mut out_file = File::create(out_file_path).expect("Could not create file");
 
     let locations: Vec<Location> = serde_json::from_str(&json_file)?;
+    let cities = parse_cities(&cities_file).report(&config.cities_file);
+    let country_info = parse_country_info(&country_info_file).report(&config.country_info_file);
+    let countries = Countries::new(&cities, country_info);
 
     // let new_locations: Vec<Location> = locations[375..425]
     let new_locations: Vec<Location> = locations
         .par_iter()
-        .map(|location| add_country(location.clone()))
+        .map(|location| add_country(location.clone(), &countries, &config))
         .collect();
     // println!("{:?}", new_locations);
 
-    write!(out_file, "{}", json!(new_locations).to_string()).expect("Unable to write to file.");
+    write!(out_file, "{}", json!(new_locations)).expect("Unable to write to file.");
 
     Ok(())
 }
 
-fn add_country(location: Location) -> Location {
-    let resp = fetch_page(&location.LocationName);
-    if resp.is_ok() {
-        let mut fetch_result = resp.unwrap();
-        if fetch_result.status().is_success() {
-            println!("Fetch -> Page: {:?}", &location.LocationName);
-            let html = fetch_result.text().unwrap();
-            let country = extract_country(html);
-            match country {
-                Some(c) => {
-                    println!("Country: {:?}", &c);
-                    Location {
-                        Country: Some(c),
-                        ..location
-                    }
-                }
-                None => {
-                    println!("No Country Found: {:?}", &location.LocationName);
-                    Location {
-                        Country: None,
-                        ..location
-                    }
-                }
+/// Country names by city name, from the geonames files.
+struct Countries<'a> {
+    names: NameIndex<'a>,
+    country_info: HashMap<&'a str, CountryInfo<'a>>,
+}
+
+impl<'a> Countries<'a> {
+    fn new(
+        cities: &[GeonamesCity<'a>],
+        country_info: HashMap<&'a str, CountryInfo<'a>>,
+    ) -> Countries<'a> {
+        Countries {
+            names: NameIndex::new(cities),
+            country_info,
+        }
+    }
+
+    fn country(&self, location_name: &str) -> Option<&'a str> {
+        // "Perth, Western Australia" is listed as "Perth".
+        let city_name = location_name.split(',').next().unwrap_or_default().trim();
+        self.names
+            .best(city_name)
+            .and_then(|city| self.country_info.get(city.country_code))
+            .map(|country_info| country_info.country)
+    }
+}
+
+fn add_country(location: Location, countries: &Countries, config: &Config) -> Location {
+    if let Some(country) = countries.country(&location.LocationName) {
+        println!(
+            "Country -> Geonames: {:?} {:?}",
+            &location.LocationName, country
+        );
+        return Location {
+            Country: Some(country.to_string()),
+            CountrySource: Some(CountrySource::Geonames),
+            ..location
+        };
+    }
+
+    let html = if !config.scrape_fallback {
+        None
+    } else if let Some(ref directory) = config.fixtures_directory {
+        read_page(directory, &location.LocationName)
+    } else {
+        fetch_html(&config.client, &config.wiki, &location.LocationName)
+    };
+
+    match html.and_then(extract_country) {
+        Some(c) => {
+            println!("Country -> Infobox: {:?} {:?}", &location.LocationName, &c);
+            Location {
+                Country: Some(c),
+                CountrySource: Some(CountrySource::Infobox),
+                ..location
             }
-        } else {
-            println!("Fetch -> StatusError: {:?}", &location.LocationName);
+        }
+        None => {
+            println!("No Country Found: {:?}", &location.LocationName);
             Location {
                 Country: None,
+                CountrySource: Some(CountrySource::Unresolved),
                 ..location
             }
         }
-    } else {
-        println!("Fetch -> FetchError: {:?}", resp);
-        Location {
-            Country: None,
-            ..location
+    }
+}
+
+fn fetch_html(client: &HttpClient, wiki: &Wiki, page: &str) -> Option<String> {
+    match fetch_page(client, wiki, page) {
+        Ok(fetch_result) => {
+            if fetch_result.status().is_success() {
+                println!("Fetch -> Page: {:?}", page);
+                Some(fetch_result.text().unwrap_or_default())
+            } else {
+                println!("Fetch -> StatusError: {:?}", page);
+                None
+            }
+        }
+        Err(err) => {
+            println!("Fetch -> FetchError: {:?}", err);
+            None
         }
     }
 }
 
-pub fn fetch_page(page: &str) -> Result<Response, reqwest::Error> {
-    let url = create_restbase_url(&page);
-    let resp = CLIENT.get(&url).send();
-    resp
+fn read_page(directory: &Path, page: &str) -> Option<String> {
+    match fs::read_to_string(fixture_file(directory, page)) {
+        Ok(html) => {
+            println!("Fetch -> Fixture: {:?}", page);
+            Some(html)
+        }
+        Err(_) => {
+            println!("Fetch -> StatusError: {:?}", page);
+            None
+        }
+    }
 }
 
-fn create_restbase_url(page: &str) -> String {
-    format!(
-        "https://en.wikipedia.org/api/rest_v1/page/html/{}?redirect=true",
-        page.replace(" ", "_")
-    )
+pub fn fetch_page(
+    client: &HttpClient,
+    wiki: &Wiki,
+    page: &str,
+) -> Result<Response, reqwest::Error> {
+    client.get(&wiki.restbase_url(page))
 }
 
 fn extract_country(html: String) -> Option<String> {
     let doc = Html::parse_document(&html);
     let table_selector = Selector::parse("table.infobox.vcard").unwrap();
     let tables = doc.select(&table_selector);
-    let tables = tables.map(|table| (table_data(table)));
-    let tables_with_country = tables
-        .filter(|table| {
-            table
-                .into_iter()
-                .any(|row| row.into_iter().any(|cell| COUNTRY.is_match(cell)))
-        })
-        .collect::<Vec<_>>();
-
-    // println!("{:?}\n", &tables_with_country);
-
-    if tables_with_country.is_empty() {
-        None
-    } else {
-        let row_with_country = tables_with_country
-            .first()
-            .unwrap()
-            .into_iter()
-            .filter(|row| row.into_iter().any(|cell| COUNTRY.is_match(cell)))
-            .collect::<Vec<_>>();
 
-        let country = row_with_country[0].last().unwrap();
+    // The value of the first row labelled "Country", e.g. ["Country", "Australia"].
+    // Other rows mentioning a country, e.g. flag captions, are not the country.
+    for table in tables {
+        let table = table_data(table);
+        let country = table
+            .iter()
+            .filter(|row| row.len() > 1 && COUNTRY.is_match(row[0]))
+            .filter_map(|row| row.last())
+            .find(|country| !COUNTRY.is_match(country));
 
-        // println!("{:?}\n", country);
-        Some(country.to_string())
+        if let Some(country) = country {
+            return Some(country.to_string());
+        }
     }
-}
-
-fn table_data(table: ElementRef) -> Vec<Vec<&str>> {
-    let row_selector = Selector::parse("tr").unwrap();
 
-    let rows = table.select(&row_selector);
-    rows.map(|row| {
-        row.text()
-            .map(|cell| cell.trim())
-            .filter(|cell| *cell != "")
-            .collect::<Vec<_>>()
-    }).collect::<Vec<_>>()
+    None
 }
 
+#[cfg(test)]
+extern crate tiny_http;
+
+#[cfg(test)]
 mod test {
-    // use super::*;
+    use super::*;
+    use std::thread;
+    use tiny_http::{Response as MockResponse, Server};
 
     #[test]
     fn case_senstive() {}
+
+    #[test]
+    fn resolves_countries_from_geonames() {
+        let cities = "2063523\tPerth\tPerth\tPerth City\t-31.95\t115.86\tP\tPPLA\tAU\t\t08\t\t\t\t1896548\t\t20\tAustralia/Perth\t2020-01-01\n\
+                      2640034\tPerth\tPerth\t\t56.39\t-3.43\tP\tPPL\tGB\t\tSCT\t\t\t\t47180\t\t20\tEurope/London\t2020-01-01\n";
+        let country_info = "#ISO\tISO3\n\
+                            AU\tAUS\t036\tAS\tAustralia\tCanberra\t7686850\t24992369\tOC\n\
+                            GB\tGBR\t826\tUK\tUnited Kingdom\tLondon\t244820\t66488991\tEU\n";
+        let cities = parse_cities(cities).rows;
+        let countries = Countries::new(&cities, parse_country_info(country_info).rows);
+
+        assert_eq!(
+            countries.country("Perth, Western Australia"),
+            Some("Australia")
+        );
+        assert_eq!(countries.country("Perth City"), Some("Australia"));
+        assert_eq!(countries.country("Atlantis"), None);
+    }
+
+    #[test]
+    fn scrapes_countries_from_a_mock_wiki() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let wiki = Wiki::with_base_url(
+            "en",
+            &format!("http://{}", server.server_addr().to_ip().unwrap()),
+        );
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let response = if request.url() == "/api/rest_v1/page/html/Oslo?redirect=true" {
+                    MockResponse::from_string(
+                        r#"<table class="infobox vcard"><tr><th>Country</th><td>Norway</td></tr></table>"#,
+                    )
+                    .boxed()
+                } else {
+                    MockResponse::empty(404).boxed()
+                };
+                let _ = request.respond(response);
+            }
+        });
+        let client = HttpClient::default();
+
+        assert_eq!(
+            fetch_html(&client, &wiki, "Oslo").and_then(extract_country),
+            Some("Norway".to_string())
+        );
+        assert_eq!(fetch_html(&client, &wiki, "Atlantis"), None);
+    }
+
+    #[test]
+    fn extracts_country_row() {
+        let html = r#"<table class="infobox vcard">
+            <tr><td>Flag of the country</td></tr>
+            <tr><th>Country</th><td><a>Norway</a></td></tr>
+        </table>"#;
+        assert_eq!(
+            extract_country(html.to_string()),
+            Some("Norway".to_string())
+        );
+
+        let html = r#"<table class="infobox vcard"><tr><th>Country</th></tr></table>"#;
+        assert_eq!(extract_country(html.to_string()), None);
+    }
 }
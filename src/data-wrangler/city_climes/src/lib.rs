@@ -0,0 +1,628 @@
+extern crate add_countries;
+extern crate city_climes_core;
+#[macro_use]
+extern crate clap;
+extern crate cross_ref_geonames_and_wikipedia_index_file;
+extern crate fetch_and_parse_climate_pages;
+extern crate geonames;
+extern crate iso_add_countries;
+extern crate parse_geonames_cities_file;
+extern crate parse_wikipedia_index_file;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate sha2;
+extern crate toml;
+
+pub mod pipeline;
+
+use city_climes_core::http::HttpClient;
+use city_climes_core::restbase::Wiki;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::Table;
+
+/// Read from the working directory when `--config` isn't given.
+pub const DEFAULT_CONFIG_FILE: &str = "city-climes.toml";
+
+pub fn app() -> App<'static, 'static> {
+    App::new("city-climes")
+        .version(crate_version!())
+        .about("Builds the City Climes data set from Wikipedia and geonames files.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .short("c")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Config file with default paths [default: city-climes.toml]"),
+        )
+        .subcommand(
+            SubCommand::with_name("index")
+                .about("Lists the Climate of and Geography of pages in a Wikipedia index file")
+                .arg(path_arg(
+                    "wikipedia-index-file",
+                    "Wikipedia dump index file",
+                ))
+                .arg(path_arg(
+                    "output-directory",
+                    "Directory to write the page lists to",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("geonames")
+                .about("Lists the Wikipedia pages that could have a geonames city's climate")
+                .arg(path_arg(
+                    "cities-file",
+                    "Geonames cities file, e.g. cities15000.txt",
+                ))
+                .arg(path_arg(
+                    "wikipedia-index-file",
+                    "Wikipedia dump index file",
+                ))
+                .arg(path_arg(
+                    "country-info-file",
+                    "Geonames countryInfo.txt file",
+                ))
+                .arg(path_arg(
+                    "admin1-file",
+                    "Geonames admin1CodesASCII.txt file, for states",
+                ))
+                .arg(path_arg(
+                    "output-directory",
+                    "Directory to write the page list to",
+                ))
+                .arg(
+                    Arg::with_name("min-population")
+                        .long("min-population")
+                        .value_name("PEOPLE")
+                        .takes_value(true)
+                        .help("Leaves out cities with fewer people"),
+                )
+                .arg(
+                    Arg::with_name("feature-code")
+                        .long("feature-code")
+                        .value_name("CODE")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Lists only cities with this geonames feature code, e.g. PPLC"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("crossref")
+                .about("Lists the Wikipedia index pages that match a geonames city, and which city")
+                .arg(path_arg(
+                    "cities-file",
+                    "Geonames cities file, e.g. cities15000.txt",
+                ))
+                .arg(alternate_names_arg())
+                .arg(path_arg(
+                    "wikipedia-index-file",
+                    "Wikipedia dump index file",
+                ))
+                .arg(path_arg(
+                    "output-directory",
+                    "Directory to write the page list to",
+                ))
+                .arg(
+                    Arg::with_name("mode")
+                        .long("mode")
+                        .takes_value(true)
+                        .possible_values(&["names", "links"])
+                        .default_value("names")
+                        .help("Matches cities by name, or by their Wikipedia links first"),
+                )
+                .arg(wiki_language_arg(
+                    "Language of the Wikipedia the index file is of, e.g. de",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("fetch")
+                .about("Fetches and parses the climate tables of a list of pages")
+                .arg(path_arg("pages-file", "File with one page name per line"))
+                .arg(path_arg(
+                    "db-path",
+                    "SQLite database to save the results to",
+                ))
+                .arg(fixtures_arg())
+                .args(&wiki_args())
+                .arg(labels_arg())
+                .arg(sections_only_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Writes the parsed locations in a database to CityClimes.json")
+                .arg(path_arg("db-path", "SQLite database of fetch results"))
+                .arg(path_arg(
+                    "output-directory",
+                    "Directory to write the JSON file to",
+                ))
+                .arg(path_arg(
+                    "registry-path",
+                    "SQLite database of the IDs given in earlier exports, to keep them the same",
+                ))
+                .arg(
+                    Arg::with_name("require-sunshine")
+                        .long("require-sunshine")
+                        .help("Leaves out locations without sunshine hours"),
+                )
+                .arg(latitude_arg(
+                    "min-latitude",
+                    "Leaves out locations south of this latitude",
+                ))
+                .arg(latitude_arg(
+                    "max-latitude",
+                    "Leaves out locations north of this latitude",
+                ))
+                .arg(
+                    Arg::with_name("pretty")
+                        .long("pretty")
+                        .help("Indents the JSON instead of writing it on one line"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("add-countries")
+                .about("Adds countries to locations from the geonames cities with their names")
+                .arg(path_arg("json-file", "CityClimes locations JSON file"))
+                .arg(path_arg(
+                    "cities-file",
+                    "Geonames cities file, e.g. cities15000.txt",
+                ))
+                .arg(path_arg(
+                    "country-info-file",
+                    "Geonames countryInfo.txt file",
+                ))
+                .arg(path_arg(
+                    "output-directory",
+                    "Directory to write the new JSON file to",
+                ))
+                .arg(scrape_countries_arg())
+                .arg(fixtures_arg())
+                .args(&wiki_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("iso-countries")
+                .about("Adds ISO country codes and continents to locations")
+                .arg(path_arg("json-file", "CityClimes locations JSON file"))
+                .arg(path_arg(
+                    "cities-file",
+                    "Geonames cities file, e.g. cities15000.txt",
+                ))
+                .arg(path_arg(
+                    "continents-file",
+                    "Continent codes and names CSV file",
+                ))
+                .arg(path_arg(
+                    "country-info-file",
+                    "Geonames countryInfo.txt file",
+                ))
+                .args(&subdivision_args())
+                .arg(path_arg(
+                    "output-directory",
+                    "Directory to write the new JSON file to",
+                ))
+                .arg(
+                    Arg::with_name("max-distance-km")
+                        .long("max-distance-km")
+                        .value_name("KM")
+                        .takes_value(true)
+                        .help("Flags page coordinates further than this from their geonames city"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("pipeline")
+                .about("Runs every stage that is out of date, from the index to the ISO codes")
+                .arg(path_arg(
+                    "wikipedia-index-file",
+                    "Wikipedia dump index file",
+                ))
+                .arg(path_arg(
+                    "cities-file",
+                    "Geonames cities file, e.g. cities15000.txt",
+                ))
+                .arg(path_arg(
+                    "continents-file",
+                    "Continent codes and names CSV file",
+                ))
+                .arg(path_arg(
+                    "country-info-file",
+                    "Geonames countryInfo.txt file",
+                ))
+                .arg(alternate_names_arg())
+                .args(&subdivision_args())
+                .arg(path_arg(
+                    "output-directory",
+                    "Directory to write each stage's files to",
+                ))
+                .arg(scrape_countries_arg())
+                .arg(fixtures_arg())
+                .args(&wiki_args())
+                .arg(labels_arg())
+                .arg(sections_only_arg())
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Runs every stage, even the up to date ones"),
+                ),
+        )
+}
+
+fn path_arg(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name)
+        .long(name)
+        .value_name("PATH")
+        .takes_value(true)
+        .help(help)
+}
+
+fn latitude_arg(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name)
+        .long(name)
+        .value_name("DEGREES")
+        .takes_value(true)
+        .allow_hyphen_values(true)
+        .help(help)
+}
+
+fn alternate_names_arg() -> Arg<'static, 'static> {
+    path_arg(
+        "alternate-names-file",
+        "Geonames alternateNamesV2.txt file, to match cities by their names in the wiki's language",
+    )
+}
+
+fn subdivision_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        path_arg(
+            "admin1-file",
+            "Geonames admin1CodesASCII.txt file, for states",
+        ),
+        path_arg("admin2-file", "Geonames admin2Codes.txt file, for counties"),
+        path_arg(
+            "subdivisions-file",
+            "Tab separated geonames admin1 codes and ISO 3166-2 codes",
+        ),
+    ]
+}
+
+fn scrape_countries_arg() -> Arg<'static, 'static> {
+    Arg::with_name("scrape-countries")
+        .long("scrape-countries")
+        .help("Scrapes the Wikipedia infobox of locations without a geonames city")
+}
+
+fn fixtures_arg() -> Arg<'static, 'static> {
+    path_arg(
+        "fixtures-directory",
+        "Read pages from saved HTML files in this directory instead of Wikipedia",
+    )
+}
+
+fn wiki_language_arg(help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("wiki-language")
+        .long("wiki-language")
+        .value_name("LANG")
+        .takes_value(true)
+        .default_value("en")
+        .help(help)
+}
+
+fn wiki_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        wiki_language_arg("Language of the Wikipedia to fetch pages from, e.g. de"),
+        Arg::with_name("wiki-base-url")
+            .long("wiki-base-url")
+            .value_name("URL")
+            .takes_value(true)
+            .help("Fetches pages from this address instead, e.g. http://127.0.0.1:8080"),
+    ]
+}
+
+fn labels_arg() -> Arg<'static, 'static> {
+    path_arg(
+        "labels-file",
+        "TOML file of climate table labels by language, instead of the built in ones",
+    )
+}
+
+fn sections_only_arg() -> Arg<'static, 'static> {
+    Arg::with_name("sections-only")
+        .long("sections-only")
+        .help("Fetches only the Climate or Geography section of each page")
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let file_config = match matches.value_of("config") {
+        Some(path) => FileConfig::load(Path::new(path))?,
+        None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+            FileConfig::load(Path::new(DEFAULT_CONFIG_FILE))?
+        }
+        None => FileConfig::default(),
+    };
+
+    match matches.subcommand() {
+        (stage, Some(stage_matches)) => {
+            let options = StageOptions {
+                stage,
+                matches: stage_matches,
+                file_config: &file_config,
+            };
+            run_stage(&options)
+        }
+        _ => Err(From::from("Didn't get a subcommand.")),
+    }
+}
+
+fn run_stage(options: &StageOptions) -> Result<(), Box<dyn Error>> {
+    match options.stage {
+        "index" => parse_wikipedia_index_file::run(parse_wikipedia_index_file::Config {
+            filename: options.path("wikipedia-index-file")?,
+            output_directory: options.output_directory()?,
+        }),
+        "geonames" => parse_geonames_cities_file::run(parse_geonames_cities_file::Config {
+            filename: options.path("cities-file")?,
+            wikipedia_index_file: options.path("wikipedia-index-file")?,
+            country_info_file: options.optional_path("country-info-file"),
+            admin1_file: options.optional_path("admin1-file"),
+            output_directory: options.output_directory()?,
+            filter: geonames::Filter {
+                feature_codes: options
+                    .matches
+                    .values_of("feature-code")
+                    .map(|codes| codes.map(str::to_string).collect())
+                    .unwrap_or_default(),
+                min_population: match options.matches.value_of("min-population") {
+                    Some(value) => value.parse().map_err(|_| {
+                        format!("--min-population should be a number, got {:?}.", value)
+                    })?,
+                    None => 0,
+                },
+            },
+        }),
+        "crossref" => cross_ref_geonames_and_wikipedia_index_file::run(
+            cross_ref_geonames_and_wikipedia_index_file::Config {
+                geonames_file: options.path("cities-file")?,
+                wikipedia_index_file: options.path("wikipedia-index-file")?,
+                alternate_names_file: options.optional_path("alternate-names-file"),
+                mode: match options.matches.value_of("mode") {
+                    Some("links") => cross_ref_geonames_and_wikipedia_index_file::Mode::Links,
+                    _ => cross_ref_geonames_and_wikipedia_index_file::Mode::Names,
+                },
+                output_directory: options.output_directory()?,
+                language: options.wiki().language,
+            },
+        ),
+        "fetch" => fetch_and_parse_climate_pages::run(fetch_and_parse_climate_pages::Config {
+            filename: options.path("pages-file")?,
+            db_path: options.path("db-path")?,
+            fixtures_directory: options.optional_path("fixtures-directory"),
+            client: HttpClient::default(),
+            wiki: options.wiki(),
+            labels_file: options.optional_path("labels-file"),
+            sections_only: options.matches.is_present("sections-only"),
+        }),
+        "export" => {
+            fetch_and_parse_climate_pages::export(fetch_and_parse_climate_pages::ExportConfig {
+                db_path: options.path("db-path")?,
+                output_directory: options.output_directory()?,
+                options: fetch_and_parse_climate_pages::ExportOptions {
+                    require_sunshine: options.matches.is_present("require-sunshine"),
+                    min_latitude: options.latitude("min-latitude")?,
+                    max_latitude: options.latitude("max-latitude")?,
+                },
+                registry_path: options.optional_path("registry-path"),
+                pretty: options.matches.is_present("pretty"),
+            })
+        }
+        "add-countries" => add_countries::run(add_countries::Config {
+            json_file: options.path("json-file")?,
+            cities_file: options.path("cities-file")?,
+            country_info_file: options.path("country-info-file")?,
+            output_directory: options.output_directory()?,
+            scrape_fallback: options.matches.is_present("scrape-countries"),
+            fixtures_directory: options.optional_path("fixtures-directory"),
+            client: HttpClient::default(),
+            wiki: options.wiki(),
+        }),
+        "iso-countries" => iso_add_countries::run(iso_add_countries::Config {
+            json_file: options.path("json-file")?,
+            cities_file: options.path("cities-file")?,
+            continents_file: options.path("continents-file")?,
+            isos_file: options.path("country-info-file")?,
+            admin1_file: options.optional_path("admin1-file"),
+            admin2_file: options.optional_path("admin2-file"),
+            subdivisions_file: options.optional_path("subdivisions-file"),
+            output_directory: options.output_directory()?,
+            max_distance_km: options.distance("max-distance-km")?,
+        }),
+        "pipeline" => {
+            let manifest = pipeline::run(&pipeline::PipelineConfig {
+                wikipedia_index_file: options.path("wikipedia-index-file")?,
+                cities_file: options.path("cities-file")?,
+                continents_file: options.path("continents-file")?,
+                country_info_file: options.path("country-info-file")?,
+                alternate_names_file: options.optional_path("alternate-names-file"),
+                admin1_file: options.optional_path("admin1-file"),
+                admin2_file: options.optional_path("admin2-file"),
+                subdivisions_file: options.optional_path("subdivisions-file"),
+                output_directory: options.output_directory()?,
+                fixtures_directory: options.optional_path("fixtures-directory"),
+                wiki: options.wiki(),
+                labels_file: options.optional_path("labels-file"),
+                sections_only: options.matches.is_present("sections-only"),
+                scrape_countries: options.matches.is_present("scrape-countries"),
+                force: options.matches.is_present("force"),
+            })?;
+            println!(
+                "Pipeline -> Done: {} stage(s) ran",
+                manifest
+                    .stages
+                    .values()
+                    .filter(|stage| stage.status == pipeline::StageStatus::Ran)
+                    .count()
+            );
+            Ok(())
+        }
+        stage => Err(From::from(format!("Unknown subcommand: {}", stage))),
+    }
+}
+
+/// Paths for a subcommand, taken from its flags or else the config file.
+struct StageOptions<'a> {
+    stage: &'a str,
+    matches: &'a ArgMatches<'a>,
+    file_config: &'a FileConfig,
+}
+
+impl<'a> StageOptions<'a> {
+    fn path(&self, flag: &str) -> Result<PathBuf, String> {
+        let key = flag.replace("-", "_");
+
+        match self.matches.value_of(flag) {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => self.file_config.get(self.stage, &key).ok_or(format!(
+                "Didn't get a {} path. Pass --{} or set {} in the config file.",
+                flag.replace("-", " "),
+                flag,
+                key
+            )),
+        }
+    }
+
+    fn optional_path(&self, flag: &str) -> Option<PathBuf> {
+        self.path(flag).ok()
+    }
+
+    fn latitude(&self, flag: &str) -> Result<Option<f64>, String> {
+        match self.matches.value_of(flag) {
+            Some(value) => match value.parse::<f64>() {
+                Ok(latitude) if (-90.0..=90.0).contains(&latitude) => Ok(Some(latitude)),
+                _ => Err(format!(
+                    "--{} should be between -90 and 90, got {:?}.",
+                    flag, value
+                )),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn distance(&self, flag: &str) -> Result<f64, String> {
+        match self.matches.value_of(flag) {
+            Some(value) => match value.parse::<f64>() {
+                Ok(distance) if distance > 0.0 => Ok(distance),
+                _ => Err(format!(
+                    "--{} should be a positive distance, got {:?}.",
+                    flag, value
+                )),
+            },
+            None => Ok(iso_add_countries::DEFAULT_MAX_DISTANCE_KM),
+        }
+    }
+
+    fn wiki(&self) -> Wiki {
+        let language = self.matches.value_of("wiki-language").unwrap_or("en");
+        match self.matches.value_of("wiki-base-url") {
+            Some(base_url) => Wiki::with_base_url(language, base_url),
+            None => Wiki::new(language),
+        }
+    }
+
+    fn output_directory(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let output_directory = self.path("output-directory")?;
+        fs::create_dir_all(&output_directory)?;
+        Ok(output_directory)
+    }
+}
+
+/// A TOML config file of default paths. A key in a subcommand's table, e.g.
+/// `[fetch] db_path`, overrides the same key at the top level, which is
+/// shared by all subcommands. Relative paths are relative to the file.
+#[derive(Debug, Default)]
+pub struct FileConfig {
+    directory: PathBuf,
+    table: Table,
+}
+
+impl FileConfig {
+    pub fn load(path: &Path) -> Result<FileConfig, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Unable to read config file {:?}: {}", path, err))?;
+        let table: Table = toml::from_str(&contents)?;
+
+        Ok(FileConfig {
+            directory: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            table,
+        })
+    }
+
+    pub fn get(&self, stage: &str, key: &str) -> Option<PathBuf> {
+        self.table
+            .get(stage)
+            .and_then(|stage_table| stage_table.get(key))
+            .or_else(|| self.table.get(key))
+            .and_then(|value| value.as_str())
+            .map(|path| self.directory.join(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_config(contents: &str) -> FileConfig {
+        FileConfig {
+            directory: PathBuf::from("config"),
+            table: toml::from_str(contents).unwrap(),
+        }
+    }
+
+    #[test]
+    fn stage_keys_override_shared_keys() {
+        let config = file_config(
+            r#"
+            output_directory = "output"
+
+            [index]
+            output_directory = "index_output"
+            "#,
+        );
+
+        assert_eq!(
+            config.get("index", "output_directory"),
+            Some(PathBuf::from("config/index_output"))
+        );
+        assert_eq!(
+            config.get("geonames", "output_directory"),
+            Some(PathBuf::from("config/output"))
+        );
+        assert_eq!(config.get("geonames", "cities_file"), None);
+    }
+
+    #[test]
+    fn flags_override_config_file() {
+        let config = file_config(r#"cities_file = "cities15000.txt""#);
+        let matches = app().get_matches_from(vec![
+            "city-climes",
+            "crossref",
+            "--cities-file",
+            "cities1000.txt",
+        ]);
+        let (stage, stage_matches) = matches.subcommand();
+        let options = StageOptions {
+            stage,
+            matches: stage_matches.unwrap(),
+            file_config: &config,
+        };
+
+        assert_eq!(
+            options.path("cities-file"),
+            Ok(PathBuf::from("cities1000.txt"))
+        );
+        assert!(options.path("wikipedia-index-file").is_err());
+    }
+}
@@ -0,0 +1,12 @@
+extern crate city_climes;
+
+use std::process;
+
+fn main() {
+    let matches = city_climes::app().get_matches();
+
+    if let Err(e) = city_climes::run(&matches) {
+        eprintln!("Application error: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,864 @@
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use add_countries;
+use city_climes_core::http::HttpClient;
+use city_climes_core::restbase::Wiki;
+use cross_ref_geonames_and_wikipedia_index_file;
+use fetch_and_parse_climate_pages;
+use iso_add_countries;
+use parse_geonames_cities_file;
+use parse_wikipedia_index_file;
+
+/// Written to the output directory after every stage.
+pub const MANIFEST_FILE: &str = "pipeline_manifest.json";
+
+/// Kept in the output directory between runs, so location IDs don't change.
+pub const REGISTRY_FILE: &str = "location_ids.db";
+
+/// Hash of what the pages in the fetch database were fetched and parsed with.
+pub const PARSER_INPUTS_FILE: &str = "parser_inputs.sha256";
+
+pub struct PipelineConfig {
+    pub wikipedia_index_file: PathBuf,
+    pub cities_file: PathBuf,
+    pub continents_file: PathBuf,
+    pub country_info_file: PathBuf,
+    /// Geonames `alternateNamesV2.txt` file, for matching cities by their
+    /// names in the wiki's language.
+    pub alternate_names_file: Option<PathBuf>,
+    /// Geonames `admin1CodesASCII.txt` and `admin2Codes.txt` files, and ISO
+    /// 3166-2 codes by admin1 code, for states and counties.
+    pub admin1_file: Option<PathBuf>,
+    pub admin2_file: Option<PathBuf>,
+    pub subdivisions_file: Option<PathBuf>,
+    /// Each stage writes to a directory named after it in here.
+    pub output_directory: PathBuf,
+    /// Read pages from saved HTML files instead of fetching them.
+    pub fixtures_directory: Option<PathBuf>,
+    /// The Wikipedia to fetch pages from, and that the index file is of.
+    pub wiki: Wiki,
+    /// Climate table labels by language, instead of the built in ones.
+    pub labels_file: Option<PathBuf>,
+    /// Fetch only the Climate or Geography section of each page.
+    pub sections_only: bool,
+    /// Scrape the Wikipedia infobox of locations without a geonames city.
+    pub scrape_countries: bool,
+    /// Run every stage, even the up to date ones.
+    pub force: bool,
+}
+
+impl PipelineConfig {
+    fn file(&self, stage: &str, filename: &str) -> PathBuf {
+        self.output_directory.join(stage).join(filename)
+    }
+
+    /// Pages are inputs too when they are read from fixtures.
+    fn with_fixtures(&self, mut inputs: Vec<PathBuf>) -> Vec<PathBuf> {
+        inputs.extend(self.fixtures_directory.clone());
+        inputs
+    }
+}
+
+/// A stage of the pipeline. A stage depends on the stages that write its
+/// inputs, so the graph follows from the files alone. A file can be both an
+/// input and an output of a stage that updates it, e.g. the ID registry.
+pub struct Stage {
+    pub name: &'static str,
+    pub inputs: Vec<PathBuf>,
+    pub outputs: Vec<PathBuf>,
+    pub run: fn(&PipelineConfig) -> Result<(), Box<dyn Error>>,
+}
+
+/// The stages that build `CityClimesCountriesISO.json`.
+pub fn stages(config: &PipelineConfig) -> Vec<Stage> {
+    vec![
+        Stage {
+            name: "index",
+            inputs: vec![config.wikipedia_index_file.clone()],
+            outputs: vec![config.file("index", "pages_to_fetch.txt")],
+            run: run_index,
+        },
+        Stage {
+            name: "geonames",
+            inputs: vec![
+                config.cities_file.clone(),
+                config.wikipedia_index_file.clone(),
+                config.country_info_file.clone(),
+            ]
+            .into_iter()
+            .chain(config.admin1_file.clone())
+            .collect(),
+            outputs: vec![config.file("geonames", "pages_to_fetch.txt")],
+            run: run_geonames,
+        },
+        Stage {
+            name: "crossref",
+            inputs: vec![
+                config.cities_file.clone(),
+                config.wikipedia_index_file.clone(),
+            ]
+            .into_iter()
+            .chain(config.alternate_names_file.clone())
+            .collect(),
+            outputs: vec![
+                config.file("crossref", "cross_ref_pages_to_fetch.txt"),
+                config.file("crossref", "cross_ref_geonames_to_wikipedia.tsv"),
+            ],
+            run: run_crossref,
+        },
+        Stage {
+            name: "fetch",
+            inputs: {
+                let mut inputs = config.with_fixtures(vec![
+                    config.file("index", "pages_to_fetch.txt"),
+                    config.file("geonames", "pages_to_fetch.txt"),
+                    config.file("crossref", "cross_ref_pages_to_fetch.txt"),
+                ]);
+                inputs.extend(config.labels_file.clone());
+                inputs
+            },
+            outputs: vec![
+                config.file("fetch", "pages_to_fetch.txt"),
+                config.file("fetch", "city_climes.db"),
+                config.file("fetch", PARSER_INPUTS_FILE),
+            ],
+            run: run_fetch,
+        },
+        // The registry is read and written by the export, so the export runs
+        // again when another one changed it, but not because it wrote it.
+        Stage {
+            name: "export",
+            inputs: vec![
+                config.file("fetch", "city_climes.db"),
+                config.output_directory.join(REGISTRY_FILE),
+            ],
+            outputs: vec![
+                config.file("export", "CityClimes.json"),
+                config.file("export", "CityClimesChanges.json"),
+                config.output_directory.join(REGISTRY_FILE),
+            ],
+            run: run_export,
+        },
+        Stage {
+            name: "add-countries",
+            inputs: {
+                let inputs = vec![
+                    config.file("export", "CityClimes.json"),
+                    config.cities_file.clone(),
+                    config.country_info_file.clone(),
+                ];
+                if config.scrape_countries {
+                    config.with_fixtures(inputs)
+                } else {
+                    inputs
+                }
+            },
+            outputs: vec![config.file("add-countries", "CityClimesCountries.json")],
+            run: run_add_countries,
+        },
+        Stage {
+            name: "iso-countries",
+            inputs: vec![
+                config.file("add-countries", "CityClimesCountries.json"),
+                config.cities_file.clone(),
+                config.continents_file.clone(),
+                config.country_info_file.clone(),
+            ]
+            .into_iter()
+            .chain(config.admin1_file.clone())
+            .chain(config.admin2_file.clone())
+            .chain(config.subdivisions_file.clone())
+            .collect(),
+            outputs: vec![
+                config.file("iso-countries", "CityClimesCountriesISO.json"),
+                config.file("iso-countries", "AmbiguousLocations.tsv"),
+                config.file("iso-countries", "LowConfidenceLocations.tsv"),
+                config.file("iso-countries", "CoordinateMismatches.tsv"),
+            ],
+            run: run_iso_countries,
+        },
+    ]
+}
+
+fn run_index(config: &PipelineConfig) -> Result<(), Box<dyn Error>> {
+    parse_wikipedia_index_file::run(parse_wikipedia_index_file::Config {
+        filename: config.wikipedia_index_file.clone(),
+        output_directory: config.output_directory.join("index"),
+    })
+}
+
+fn run_geonames(config: &PipelineConfig) -> Result<(), Box<dyn Error>> {
+    parse_geonames_cities_file::run(parse_geonames_cities_file::Config {
+        filename: config.cities_file.clone(),
+        wikipedia_index_file: config.wikipedia_index_file.clone(),
+        country_info_file: Some(config.country_info_file.clone()),
+        admin1_file: config.admin1_file.clone(),
+        output_directory: config.output_directory.join("geonames"),
+        filter: Default::default(),
+    })
+}
+
+fn run_crossref(config: &PipelineConfig) -> Result<(), Box<dyn Error>> {
+    cross_ref_geonames_and_wikipedia_index_file::run(
+        cross_ref_geonames_and_wikipedia_index_file::Config {
+            geonames_file: config.cities_file.clone(),
+            wikipedia_index_file: config.wikipedia_index_file.clone(),
+            alternate_names_file: config.alternate_names_file.clone(),
+            // Links are more reliable than names, when there are any.
+            mode: match config.alternate_names_file {
+                Some(_) => cross_ref_geonames_and_wikipedia_index_file::Mode::Links,
+                None => cross_ref_geonames_and_wikipedia_index_file::Mode::Names,
+            },
+            output_directory: config.output_directory.join("crossref"),
+            language: config.wiki.language.clone(),
+        },
+    )
+}
+
+fn run_fetch(config: &PipelineConfig) -> Result<(), Box<dyn Error>> {
+    // Climate pages first, then the candidate and the cross referenced city
+    // pages, each once.
+    let mut seen = HashSet::new();
+    let mut pages = Vec::new();
+    for list in &[
+        config.file("index", "pages_to_fetch.txt"),
+        config.file("geonames", "pages_to_fetch.txt"),
+        config.file("crossref", "cross_ref_pages_to_fetch.txt"),
+    ] {
+        for page in fs::read_to_string(list)?.lines() {
+            if seen.insert(page.to_string()) {
+                pages.push(page.to_string());
+            }
+        }
+    }
+
+    let pages_file = config.file("fetch", "pages_to_fetch.txt");
+    let mut f = File::create(&pages_file)?;
+    for page in &pages {
+        write!(f, "{}\r\n", page)?;
+    }
+
+    // The database is kept, so pages saved in an earlier run aren't fetched
+    // again, but pages dropped from the lists don't linger in it. It is
+    // started afresh when pages would be fetched or parsed differently.
+    let db_path = config.file("fetch", "city_climes.db");
+    let parser_inputs_file = config.file("fetch", PARSER_INPUTS_FILE);
+    let parser_inputs = parser_inputs_hash(config)?;
+    if db_path.exists() {
+        if fs::read_to_string(&parser_inputs_file).ok().as_ref() == Some(&parser_inputs) {
+            fetch_and_parse_climate_pages::remove_unlisted_pages(&db_path, &seen);
+        } else {
+            println!("Fetch -> Parser Inputs Changed: starting a fresh database");
+            fs::remove_file(&db_path)?;
+        }
+    }
+
+    fetch_and_parse_climate_pages::run(fetch_and_parse_climate_pages::Config {
+        filename: pages_file,
+        db_path,
+        fixtures_directory: config.fixtures_directory.clone(),
+        client: HttpClient::default(),
+        wiki: config.wiki.clone(),
+        labels_file: config.labels_file.clone(),
+        sections_only: config.sections_only,
+    })?;
+
+    fs::write(&parser_inputs_file, parser_inputs)?;
+    Ok(())
+}
+
+/// SHA-256 of what decides the fetched pages' results: the wiki, whether
+/// only sections are fetched, the labels and the fixtures, if any.
+fn parser_inputs_hash(config: &PipelineConfig) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(
+        format!(
+            "{}\n{}\nsections_only: {}\n",
+            config.wiki.base_url, config.wiki.language, config.sections_only
+        )
+        .as_bytes(),
+    );
+    if let Some(ref labels_file) = config.labels_file {
+        hasher.update(b"labels\n");
+        hash_into(&mut hasher, labels_file)?;
+    }
+    if let Some(ref fixtures_directory) = config.fixtures_directory {
+        hasher.update(b"fixtures\n");
+        hash_into(&mut hasher, fixtures_directory)?;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn run_export(config: &PipelineConfig) -> Result<(), Box<dyn Error>> {
+    fetch_and_parse_climate_pages::export(fetch_and_parse_climate_pages::ExportConfig {
+        db_path: config.file("fetch", "city_climes.db"),
+        output_directory: config.output_directory.join("export"),
+        options: fetch_and_parse_climate_pages::ExportOptions::default(),
+        registry_path: Some(config.output_directory.join(REGISTRY_FILE)),
+        pretty: false,
+    })
+}
+
+fn run_add_countries(config: &PipelineConfig) -> Result<(), Box<dyn Error>> {
+    add_countries::run(add_countries::Config {
+        json_file: config.file("export", "CityClimes.json"),
+        cities_file: config.cities_file.clone(),
+        country_info_file: config.country_info_file.clone(),
+        output_directory: config.output_directory.join("add-countries"),
+        scrape_fallback: config.scrape_countries,
+        fixtures_directory: config.fixtures_directory.clone(),
+        client: HttpClient::default(),
+        wiki: config.wiki.clone(),
+    })
+}
+
+fn run_iso_countries(config: &PipelineConfig) -> Result<(), Box<dyn Error>> {
+    iso_add_countries::run(iso_add_countries::Config {
+        json_file: config.file("add-countries", "CityClimesCountries.json"),
+        cities_file: config.cities_file.clone(),
+        continents_file: config.continents_file.clone(),
+        isos_file: config.country_info_file.clone(),
+        admin1_file: config.admin1_file.clone(),
+        admin2_file: config.admin2_file.clone(),
+        subdivisions_file: config.subdivisions_file.clone(),
+        output_directory: config.output_directory.join("iso-countries"),
+        max_distance_km: iso_add_countries::DEFAULT_MAX_DISTANCE_KM,
+    })
+}
+
+/// Stage indexes in the order they can run: after the stages that write
+/// their inputs and otherwise in the order they are listed.
+pub fn order(stages: &[Stage]) -> Result<Vec<usize>, String> {
+    let mut writers = BTreeMap::new();
+    for (index, stage) in stages.iter().enumerate() {
+        for output in &stage.outputs {
+            if let Some(other) = writers.insert(output, index) {
+                return Err(format!(
+                    "The {} and {} stages both write {:?}.",
+                    stages[other].name, stage.name, output
+                ));
+            }
+        }
+    }
+
+    let depends_on: Vec<Vec<usize>> = stages
+        .iter()
+        .enumerate()
+        .map(|(index, stage)| {
+            stage
+                .inputs
+                .iter()
+                .filter_map(|input| writers.get(input).cloned())
+                .filter(|writer| *writer != index)
+                .collect()
+        })
+        .collect();
+
+    let mut ordered: Vec<usize> = Vec::new();
+    while ordered.len() < stages.len() {
+        let next = (0..stages.len()).find(|index| {
+            !ordered.contains(index)
+                && depends_on[*index]
+                    .iter()
+                    .all(|dependency| ordered.contains(dependency))
+        });
+
+        match next {
+            Some(index) => ordered.push(index),
+            None => {
+                let waiting: Vec<&str> = (0..stages.len())
+                    .filter(|index| !ordered.contains(index))
+                    .map(|index| stages[index].name)
+                    .collect();
+                return Err(format!(
+                    "The {} stages depend on each other.",
+                    waiting.join(", ")
+                ));
+            }
+        }
+    }
+
+    Ok(ordered)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StageStatus {
+    Ran,
+    Skipped,
+}
+
+/// What a stage read and wrote the last time it ran, by content hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageRecord {
+    pub status: StageStatus,
+    /// Why the stage ran, e.g. which inputs changed.
+    pub reason: Option<String>,
+    pub inputs: BTreeMap<String, String>,
+    pub outputs: BTreeMap<String, String>,
+    pub seconds: f64,
+    /// Unix time the stage last finished running.
+    pub finished_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub started_at: u64,
+    pub stages: BTreeMap<String, StageRecord>,
+}
+
+impl Manifest {
+    fn load(path: &Path) -> Manifest {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Runs the stages whose inputs or outputs changed since the last run, in
+/// dependency order, and records each one in the run manifest.
+pub fn run(config: &PipelineConfig) -> Result<Manifest, Box<dyn Error>> {
+    let stages = stages(config);
+    let order = order(&stages)?;
+
+    fs::create_dir_all(&config.output_directory)?;
+    let manifest_path = config.output_directory.join(MANIFEST_FILE);
+    let previous = Manifest::load(&manifest_path);
+    let mut manifest = Manifest {
+        started_at: unix_time(),
+        ..Default::default()
+    };
+
+    for index in order {
+        let stage = &stages[index];
+        // An input the stage writes itself is missing until it first runs.
+        let inputs: Vec<PathBuf> = stage
+            .inputs
+            .iter()
+            .filter(|input| !stage.outputs.contains(input) || input.exists())
+            .cloned()
+            .collect();
+        let mut inputs = hash_files(&inputs)
+            .map_err(|err| format!("Missing an input of the {} stage: {}", stage.name, err))?;
+
+        let previous_record = previous.stages.get(stage.name);
+        let reason = if config.force {
+            Some("forced".to_string())
+        } else {
+            stale_reason(stage, previous_record, &inputs)
+        };
+
+        let record = match (reason, previous_record) {
+            (None, Some(previous_record)) => {
+                println!("Pipeline -> Skip: {} is up to date", stage.name);
+                StageRecord {
+                    status: StageStatus::Skipped,
+                    reason: None,
+                    ..previous_record.clone()
+                }
+            }
+            (reason, _) => {
+                let reason = reason.unwrap_or_else(|| "never run".to_string());
+                println!("Pipeline -> Run: {} ({})", stage.name, reason);
+                for output in &stage.outputs {
+                    if let Some(directory) = output.parent() {
+                        fs::create_dir_all(directory)?;
+                    }
+                }
+
+                let start = Instant::now();
+                if let Err(err) = (stage.run)(config) {
+                    manifest.save(&manifest_path)?;
+                    return Err(From::from(format!(
+                        "The {} stage failed: {}",
+                        stage.name, err
+                    )));
+                }
+
+                let outputs = hash_files(&stage.outputs).map_err(|err| {
+                    format!("The {} stage didn't write an output: {}", stage.name, err)
+                })?;
+                // Inputs the stage wrote are recorded as it left them.
+                for input in &stage.inputs {
+                    let key = input.display().to_string();
+                    if let Some(hash) = outputs.get(&key) {
+                        inputs.insert(key, hash.clone());
+                    }
+                }
+
+                StageRecord {
+                    status: StageStatus::Ran,
+                    reason: Some(reason),
+                    inputs,
+                    outputs,
+                    seconds: start.elapsed().as_secs_f64(),
+                    finished_at: unix_time(),
+                }
+            }
+        };
+
+        manifest.stages.insert(stage.name.to_string(), record);
+        manifest.save(&manifest_path)?;
+    }
+
+    Ok(manifest)
+}
+
+fn stale_reason(
+    stage: &Stage,
+    previous: Option<&StageRecord>,
+    inputs: &BTreeMap<String, String>,
+) -> Option<String> {
+    let previous = previous?;
+
+    let changed_inputs: Vec<&str> = inputs
+        .iter()
+        .filter(|(path, hash)| previous.inputs.get(*path) != Some(hash))
+        .map(|(path, _)| path.as_str())
+        .collect();
+    if !changed_inputs.is_empty() || inputs.len() != previous.inputs.len() {
+        return Some(format!("inputs changed: {}", changed_inputs.join(", ")));
+    }
+
+    for output in &stage.outputs {
+        let key = output.display().to_string();
+        match hash_path(output) {
+            Ok(ref hash) if previous.outputs.get(&key) == Some(hash) => {}
+            Ok(_) => return Some(format!("output changed: {}", key)),
+            Err(_) => return Some(format!("output missing: {}", key)),
+        }
+    }
+
+    None
+}
+
+fn hash_files(paths: &[PathBuf]) -> io::Result<BTreeMap<String, String>> {
+    paths
+        .iter()
+        .map(|path| {
+            hash_path(path)
+                .map(|hash| (path.display().to_string(), hash))
+                .map_err(|err| io::Error::new(err.kind(), format!("{:?}: {}", path, err)))
+        })
+        .collect()
+}
+
+/// SHA-256 of a file, or of the names and contents of a directory's files.
+fn hash_path(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    hash_into(&mut hasher, path)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn hash_into(hasher: &mut Sha256, path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        for entry in entries {
+            hasher.update(
+                entry
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .as_bytes(),
+            );
+            hash_into(hasher, &entry)?;
+        }
+    } else {
+        io::copy(&mut File::open(path)?, hasher)?;
+    }
+
+    Ok(())
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use city_climes_core::Location;
+    use std::env;
+    use std::sync::OnceLock;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(path)
+    }
+
+    fn no_op(_: &PipelineConfig) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn stage(name: &'static str, inputs: &[&str], outputs: &[&str]) -> Stage {
+        Stage {
+            name,
+            inputs: inputs.iter().map(PathBuf::from).collect(),
+            outputs: outputs.iter().map(PathBuf::from).collect(),
+            run: no_op,
+        }
+    }
+
+    #[test]
+    fn orders_stages_by_their_files() {
+        let stages = vec![
+            stage("export", &["db"], &["json"]),
+            stage("fetch", &["pages"], &["db"]),
+            stage("index", &["index"], &["pages"]),
+            stage("geonames", &["cities"], &["names"]),
+        ];
+        assert_eq!(order(&stages), Ok(vec![2, 1, 0, 3]));
+
+        let stages = vec![
+            stage("export", &["db", "ids"], &["json", "ids"]),
+            stage("fetch", &["pages"], &["db"]),
+        ];
+        assert_eq!(order(&stages), Ok(vec![1, 0]));
+
+        let stages = vec![
+            stage("index", &["json"], &["pages"]),
+            stage("export", &["pages"], &["json"]),
+        ];
+        assert!(order(&stages).is_err());
+    }
+
+    /// The pipeline's config for the fixtures, writing to a directory of its
+    /// own with a copy of the continents file that tests can change.
+    fn fixtures_config(name: &str) -> PipelineConfig {
+        let output_directory = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&output_directory);
+        fs::create_dir_all(&output_directory).unwrap();
+        let continents_file = output_directory.join("continents.csv");
+        fs::copy(fixture("continents.csv"), &continents_file).unwrap();
+
+        PipelineConfig {
+            wikipedia_index_file: fixture("enwiki-index.txt"),
+            cities_file: fixture("cities.txt"),
+            continents_file,
+            country_info_file: fixture("countryInfo.txt"),
+            alternate_names_file: Some(fixture("alternateNames.txt")),
+            admin1_file: Some(fixture("admin1CodesASCII.txt")),
+            admin2_file: None,
+            subdivisions_file: Some(fixture("subdivisions.tsv")),
+            output_directory: output_directory.join("output"),
+            fixtures_directory: Some(fixture("pages")),
+            wiki: Wiki::default(),
+            labels_file: None,
+            sections_only: false,
+            scrape_countries: true,
+            force: false,
+        }
+    }
+
+    /// The fixtures run through the pipeline once, shared by the tests that
+    /// only read its outputs.
+    fn fixtures_run() -> &'static PipelineConfig {
+        static RUN: OnceLock<PipelineConfig> = OnceLock::new();
+        RUN.get_or_init(|| {
+            let config = fixtures_config("city_climes_fixtures_run");
+            let manifest = run(&config).unwrap();
+            assert!(manifest
+                .stages
+                .values()
+                .all(|stage| stage.status == StageStatus::Ran));
+            config
+        })
+    }
+
+    fn read_locations(path: &Path) -> Vec<Location> {
+        let mut locations: Vec<Location> =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        locations.sort_by(|a, b| a.LocationName.cmp(&b.LocationName));
+        locations
+    }
+
+    #[test]
+    fn resolves_countries_and_continents() {
+        let config = fixtures_run();
+        let locations =
+            read_locations(&config.file("iso-countries", "CityClimesCountriesISO.json"));
+        let summary: Vec<_> = locations
+            .iter()
+            .map(|location| {
+                (
+                    location.LocationName.as_str(),
+                    location.Country.as_deref(),
+                    location.Continent.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Darwin", Some("Australia"), Some("Oceania")),
+                ("Oslo", Some("Norway"), Some("Europe")),
+                ("Perth", Some("Australia"), Some("Oceania")),
+            ]
+        );
+        assert_eq!(locations[1].AverageLowC[0], Some(-6.5));
+        assert_eq!(locations[1].GeonameID, Some(3143244));
+        assert_eq!(locations[1].Timezone, Some("Europe/Oslo".to_string()));
+        assert_eq!(locations[1].ISOAlpha3, Some("NOR".to_string()));
+        assert_eq!(locations[2].Admin1, Some("Western Australia".to_string()));
+        assert_eq!(locations[2].Admin1ISOCode, Some("AU-WA".to_string()));
+    }
+
+    #[test]
+    fn assigns_ids_from_the_registry() {
+        let config = fixtures_run();
+        let locations = read_locations(&config.file("export", "CityClimes.json"));
+        let ids: Vec<u32> = locations.iter().map(|location| location.ID).collect();
+        assert_eq!(ids, vec![1006, 1003, 1001]);
+        assert!(config.output_directory.join(REGISTRY_FILE).exists());
+    }
+
+    #[test]
+    fn lists_candidate_pages_in_the_index() {
+        let config = fixtures_run();
+        let candidates = config.file("geonames", "pages_to_fetch.txt");
+        assert_eq!(
+            fs::read_to_string(&candidates).unwrap(),
+            "Climate of Perth\r\nDarwin, Northern Territory\r\nOslo\r\nPerth\r\n"
+        );
+    }
+
+    #[test]
+    fn fetches_candidate_pages() {
+        // Darwin's page is only listed as a candidate, as it has no "Climate
+        // of" page and no link in the alternate names file.
+        let config = fixtures_run();
+        let listed = |stage: &str, file: &str| {
+            fs::read_to_string(config.file(stage, file))
+                .unwrap()
+                .lines()
+                .any(|page| page == "Darwin, Northern Territory")
+        };
+        assert!(!listed("index", "pages_to_fetch.txt"));
+        assert!(!listed("crossref", "cross_ref_pages_to_fetch.txt"));
+        assert!(listed("fetch", "pages_to_fetch.txt"));
+
+        let exported = read_locations(&config.file("export", "CityClimes.json"));
+        let darwin = exported
+            .iter()
+            .find(|l| l.LocationName == "Darwin, Northern Territory")
+            .unwrap();
+        assert_eq!(darwin.ID, 1006);
+        assert_eq!(darwin.AverageHighC[0], Some(31.8));
+        assert_eq!(darwin.AverageLowF[11], Some(77.5));
+        assert_eq!(
+            darwin.SunshineHours.as_ref().map(|hours| hours[7]),
+            Some(Some(319.3))
+        );
+
+        let locations =
+            read_locations(&config.file("iso-countries", "CityClimesCountriesISO.json"));
+        let darwin = locations
+            .iter()
+            .find(|l| l.LocationName == "Darwin")
+            .unwrap();
+        assert_eq!(darwin.GeonameID, Some(2073124));
+        assert_eq!(darwin.Admin1ISOCode, Some("AU-NT".to_string()));
+    }
+
+    #[test]
+    fn cross_references_geonames_and_wikipedia() {
+        let config = fixtures_run();
+        let mapping = config.file("crossref", "cross_ref_geonames_to_wikipedia.tsv");
+        assert_eq!(
+            fs::read_to_string(&mapping).unwrap(),
+            "GeonameID\tCityName\tCountryCode\tWikipediaTitle\tMatchedFields\r\n\
+             2063523\tPerth\tAU\tPerth\tlink\r\n\
+             3143244\tOslo\tNO\tOslo\tlink\r\n"
+        );
+    }
+
+    #[test]
+    fn exports_page_coordinates() {
+        let config = fixtures_run();
+        let exported = read_locations(&config.file("export", "CityClimes.json"));
+        let oslo = exported.iter().find(|l| l.LocationName == "Oslo").unwrap();
+        assert_eq!(
+            oslo.Latitude.zip(oslo.Longitude),
+            Some((59.91273, 10.74609))
+        );
+
+        // Oslo's page coordinates agree with geonames.
+        let mismatches = config.file("iso-countries", "CoordinateMismatches.tsv");
+        assert_eq!(fs::read_to_string(&mismatches).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn runs_only_stages_whose_files_changed() {
+        let config = fixtures_config("city_climes_runs_only_stages_whose_files_changed");
+        run(&config).unwrap();
+
+        // Nothing changed, so nothing runs again.
+        let manifest = run(&config).unwrap();
+        assert!(manifest
+            .stages
+            .values()
+            .all(|stage| stage.status == StageStatus::Skipped));
+
+        // Only the stage that reads the changed file runs again.
+        fs::write(&config.continents_file, "EU,Europa\nOC,Oceania\n").unwrap();
+        let manifest = run(&config).unwrap();
+        let ran: Vec<&String> = manifest
+            .stages
+            .iter()
+            .filter(|(_, stage)| stage.status == StageStatus::Ran)
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(ran, vec!["iso-countries"]);
+    }
+
+    #[test]
+    fn parses_pages_again_when_the_labels_change() {
+        let config = fixtures_config("city_climes_parses_pages_again_when_the_labels_change");
+        let labels_file = config.output_directory.with_file_name("labels.toml");
+        let config = PipelineConfig {
+            labels_file: Some(labels_file.clone()),
+            ..config
+        };
+        let labels = fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../fetch_and_parse_climate_pages/src/parse_pages/labels.toml"),
+        )
+        .unwrap();
+        fs::write(&labels_file, &labels).unwrap();
+        run(&config).unwrap();
+        let exported = read_locations(&config.file("export", "CityClimes.json"));
+        assert!(exported.iter().all(|l| l.SunshineHours.is_some()));
+
+        // Without a sunshine label, the saved pages parse without sunshine.
+        fs::write(
+            &labels_file,
+            labels.replace("sunshine = '(?i)sunshine hours'", "sunshine = '^$'"),
+        )
+        .unwrap();
+        run(&config).unwrap();
+        let exported = read_locations(&config.file("export", "CityClimes.json"));
+        assert_eq!(exported.len(), 3);
+        assert!(exported.iter().all(|l| l.SunshineHours.is_none()));
+    }
+}
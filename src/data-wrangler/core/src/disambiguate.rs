@@ -0,0 +1,246 @@
+//! Picks which of the cities with a location's name the location is.
+
+use geonames::{CountryInfo, NameMatch, Subdivisions};
+use std::collections::HashMap;
+
+/// Decisions with a lower confidence are worth checking by hand.
+pub const LOW_CONFIDENCE: f64 = 0.6;
+
+const TITLE_WEIGHT: f64 = 3.0;
+const INFOBOX_WEIGHT: f64 = 3.0;
+const NEAR_WEIGHT: f64 = 4.0;
+const NEAR_KM: f64 = 50.0;
+const NEARBY_WEIGHT: f64 = 2.0;
+const NEARBY_KM: f64 = 250.0;
+
+/// What the Wikipedia page says about where a location is.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Signals<'a> {
+    /// The rest of the title after the first comma, e.g. "Western Australia"
+    /// in "Perth, Western Australia".
+    pub title_suffix: Option<&'a str>,
+    /// The country from the page's infobox.
+    pub infobox_country: Option<&'a str>,
+    /// The page's coordinates as (latitude, longitude).
+    pub coordinates: Option<(f64, f64)>,
+}
+
+impl<'a> Signals<'a> {
+    pub fn from_title(title: &'a str) -> Signals<'a> {
+        Signals {
+            title_suffix: title.split_once(',').map(|(_, suffix)| suffix.trim()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision<'a> {
+    pub city: NameMatch<'a>,
+    /// The chosen city's share of the candidates' scores, from 0 to 1.
+    pub confidence: f64,
+    /// The signals that pointed to the chosen city.
+    pub reasons: Vec<String>,
+}
+
+pub struct Disambiguator<'a> {
+    countries: &'a HashMap<&'a str, CountryInfo<'a>>,
+    subdivisions: Option<&'a Subdivisions<'a>>,
+}
+
+impl<'a> Disambiguator<'a> {
+    pub fn new(countries: &'a HashMap<&'a str, CountryInfo<'a>>) -> Disambiguator<'a> {
+        Disambiguator {
+            countries,
+            subdivisions: None,
+        }
+    }
+
+    /// Lets titles and infoboxes name a city's state or province as well as
+    /// its country, e.g. "Perth, Western Australia".
+    pub fn with_subdivisions(self, subdivisions: &'a Subdivisions<'a>) -> Disambiguator<'a> {
+        Disambiguator {
+            subdivisions: Some(subdivisions),
+            ..self
+        }
+    }
+
+    /// Scores each candidate on population and how well it fits the signals,
+    /// and picks the best. Ties go to the first candidate, the most populous.
+    pub fn decide(&self, candidates: &[NameMatch<'a>], signals: &Signals) -> Option<Decision<'a>> {
+        let total_population: u64 = candidates.iter().map(|city| city.population).sum();
+
+        let scored: Vec<(f64, Vec<String>)> = candidates
+            .iter()
+            .map(|city| {
+                let mut reasons = Vec::new();
+                let share = if total_population > 0 {
+                    city.population as f64 / total_population as f64
+                } else {
+                    1.0 / candidates.len() as f64
+                };
+                let mut score = share;
+                reasons.push(format!("{:.0}% of the population", share * 100.0));
+
+                if let Some(suffix) = signals.title_suffix {
+                    if self.names_place(suffix, city) {
+                        score += TITLE_WEIGHT;
+                        reasons.push(format!("title names {}", suffix));
+                    }
+                }
+                if let Some(country) = signals.infobox_country {
+                    if self.names_place(country, city) {
+                        score += INFOBOX_WEIGHT;
+                        reasons.push(format!("infobox names {}", country));
+                    }
+                }
+                if let Some(coordinates) = signals.coordinates {
+                    let km = distance_km(coordinates, (city.latitude, city.longitude));
+                    if km <= NEAR_KM {
+                        score += NEAR_WEIGHT;
+                    } else if km <= NEARBY_KM {
+                        score += NEARBY_WEIGHT;
+                    }
+                    reasons.push(format!("{:.0} km from the page's coordinates", km));
+                }
+
+                (score, reasons)
+            })
+            .collect();
+
+        let total_score: f64 = scored.iter().map(|(score, _)| score).sum();
+        let mut best: Option<usize> = None;
+        for (index, (score, _)) in scored.iter().enumerate() {
+            if best.is_none_or(|best| *score > scored[best].0) {
+                best = Some(index);
+            }
+        }
+
+        best.map(|index| Decision {
+            city: candidates[index],
+            confidence: scored[index].0 / total_score,
+            reasons: scored[index].1.clone(),
+        })
+    }
+
+    /// Whether a place name from the page, e.g. "Western Australia" or
+    /// "Australia", names where the city is.
+    fn names_place(&self, place: &str, city: &NameMatch) -> bool {
+        let admin1 = self
+            .subdivisions
+            .and_then(|subdivisions| subdivisions.admin1(city.country_code, city.admin1_code));
+
+        let place = place.trim();
+        place
+            .split(',')
+            .map(str::trim)
+            .chain(Some(place))
+            .any(|part| {
+                part.eq_ignore_ascii_case(city.country_code)
+                    || self
+                        .countries
+                        .get(city.country_code)
+                        .is_some_and(|info| part.eq_ignore_ascii_case(info.country))
+                    || admin1.is_some_and(|admin1| {
+                        part.eq_ignore_ascii_case(admin1.name)
+                            || part.eq_ignore_ascii_case(admin1.ascii_name)
+                    })
+            })
+    }
+}
+
+/// Great-circle distance between two (latitude, longitude) points in km.
+pub fn distance_km(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let earth_radius_km = 6371.0;
+    let (lat1, lon1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lon2) = (b.0.to_radians(), b.1.to_radians());
+
+    let h = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * earth_radius_km * h.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geonames::{parse_admin_codes, parse_cities, parse_country_info, NameIndex};
+
+    const CITIES: &str = "\
+2063523\tPerth\tPerth\t\t-31.95224\t115.8614\tP\tPPLA\tAU\t\t08\t\t\t\t1896548\t\t20\tAustralia/Perth\t2020-01-01
+2640034\tPerth\tPerth\t\t56.39522\t-3.43139\tP\tPPL\tGB\t\tSCT\t\t\t\t47180\t\t20\tEurope/London\t2020-01-01
+";
+    const COUNTRY_INFO: &str = "\
+AU\tAUS\t036\tAS\tAustralia\tCanberra\t7686850\t24992369\tOC
+GB\tGBR\t826\tUK\tUnited Kingdom\tLondon\t244820\t66488991\tEU
+";
+
+    #[test]
+    fn signals_outweigh_population() {
+        let cities = parse_cities(CITIES).rows;
+        let names = NameIndex::new(&cities);
+        let countries = parse_country_info(COUNTRY_INFO).rows;
+        let disambiguator = Disambiguator::new(&countries);
+
+        let decision = disambiguator
+            .decide(names.matches("Perth"), &Signals::from_title("Perth"))
+            .unwrap();
+        assert_eq!(decision.city.country_code, "AU");
+        assert!(decision.confidence > 0.9);
+
+        let decision = disambiguator
+            .decide(
+                names.matches("Perth"),
+                &Signals::from_title("Perth, United Kingdom"),
+            )
+            .unwrap();
+        assert_eq!(decision.city.country_code, "GB");
+
+        let signals = Signals {
+            coordinates: Some((56.4, -3.4)),
+            ..Default::default()
+        };
+        let decision = disambiguator
+            .decide(names.matches("Perth"), &signals)
+            .unwrap();
+        assert_eq!(decision.city.country_code, "GB");
+        assert!(decision.confidence < 0.9);
+
+        assert_eq!(disambiguator.decide(&[], &signals), None);
+    }
+
+    #[test]
+    fn titles_can_name_states() {
+        let cities = parse_cities(CITIES).rows;
+        let names = NameIndex::new(&cities);
+        let countries = parse_country_info(COUNTRY_INFO).rows;
+        let subdivisions = Subdivisions {
+            admin1: parse_admin_codes(
+                "AU.08\tWestern Australia\tWestern Australia\t2058645\n\
+                 GB.SCT\tScotland\tScotland\t2638360\n",
+            )
+            .rows,
+            ..Default::default()
+        };
+        let signals = Signals::from_title("Perth, Scotland");
+
+        let decision = Disambiguator::new(&countries)
+            .decide(names.matches("Perth"), &signals)
+            .unwrap();
+        assert_eq!(decision.city.country_code, "AU");
+
+        let decision = Disambiguator::new(&countries)
+            .with_subdivisions(&subdivisions)
+            .decide(names.matches("Perth"), &signals)
+            .unwrap();
+        assert_eq!(decision.city.country_code, "GB");
+        assert!(decision
+            .reasons
+            .contains(&"title names Scotland".to_string()));
+    }
+
+    #[test]
+    fn measures_distances() {
+        let km = distance_km((51.5074, -0.1278), (48.8566, 2.3522));
+        assert!((km - 343.5).abs() < 1.0);
+    }
+}
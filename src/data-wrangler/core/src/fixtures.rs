@@ -0,0 +1,7 @@
+use std::path::{Path, PathBuf};
+
+/// A saved copy of a page's RESTBase HTML in a fixtures directory, named as
+/// in the page's URL, e.g. `Climate_of_Perth.html`.
+pub fn fixture_file(directory: &Path, page: &str) -> PathBuf {
+    directory.join(format!("{}.html", page.replace(" ", "_")))
+}
@@ -0,0 +1,67 @@
+use reqwest::blocking::{Client, Response};
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for a response before giving up on a page.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many times a request is retried when Wikipedia rate limits it.
+pub const MAX_RETRIES: u32 = 3;
+
+/// The longest wait asked for by a Retry-After header that is honoured.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// The HTTP client pages are fetched with. Rate limited requests, i.e. 429
+/// Too Many Requests, are retried after the wait in their Retry-After header.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client,
+    max_retries: u32,
+}
+
+impl HttpClient {
+    pub fn new(timeout: Duration) -> HttpClient {
+        HttpClient {
+            client: Client::builder()
+                .timeout(timeout)
+                .build()
+                .expect("Failed to build HTTP client."),
+            max_retries: MAX_RETRIES,
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Result<Response, reqwest::Error> {
+        let mut retries = 0;
+        loop {
+            let response = self.client.get(url).send()?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS || retries == self.max_retries {
+                return Ok(response);
+            }
+
+            let wait = retry_after(&response);
+            println!("Fetch -> RateLimited: {:?}, retrying in {:?}", url, wait);
+            thread::sleep(wait);
+            retries += 1;
+        }
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> HttpClient {
+        HttpClient::new(DEFAULT_TIMEOUT)
+    }
+}
+
+/// The wait in seconds a Retry-After header asks for, or a second when it
+/// has none or a date instead.
+fn retry_after(response: &Response) -> Duration {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map_or(Duration::from_secs(1), Duration::from_secs)
+        .min(MAX_RETRY_AFTER)
+}
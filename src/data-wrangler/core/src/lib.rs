@@ -0,0 +1,65 @@
+extern crate csv;
+extern crate geonames;
+#[macro_use]
+extern crate lazy_static;
+extern crate regex;
+extern crate reqwest;
+extern crate rusqlite;
+extern crate scraper;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+pub mod disambiguate;
+pub mod fixtures;
+pub mod http;
+mod location;
+mod page_result;
+pub mod records;
+pub mod restbase;
+mod table;
+
+pub use location::{CountrySource, Location};
+pub use page_result::*;
+pub use table::{table_data, FOOTNOTE};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_months_deserialize_as_none() {
+        let location: Location = serde_json::from_str(
+            r#"{
+                "ID": 1,
+                "WikipediaURL": "https://en.wikipedia.org/wiki/Climate_of_Perth",
+                "LocationName": "Perth",
+                "AverageHighC": [30.1, null],
+                "AverageLowC": [17.0, 17.4],
+                "AverageHighF": [86.2, null],
+                "AverageLowF": [62.6, 63.3],
+                "SunshineHours": null,
+                "Country": "Australia"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(location.AverageHighC, vec![Some(30.1), None]);
+        assert_eq!(location.ISOCode, None);
+    }
+
+    #[test]
+    fn canonical_keys_use_page_id_and_url() {
+        let page_result = PageResult {
+            page_id: Some(645042),
+            content_location_url: Some("https://en.wikipedia.org/wiki/Perth".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            page_result.canonical_keys(),
+            vec!["page_id:645042", "url:https://en.wikipedia.org/wiki/Perth"]
+        );
+    }
+}
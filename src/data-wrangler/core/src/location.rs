@@ -0,0 +1,63 @@
+/// A location in the CityClimes JSON file, as read and written by the
+/// country stages and used by the website.
+///
+/// Month series hold `null` for months missing from the Wikipedia table.
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Location {
+    pub ID: u32,
+    pub WikipediaURL: String,
+    pub LocationName: String,
+    pub AverageHighC: Vec<Option<f64>>,
+    pub AverageLowC: Vec<Option<f64>>,
+    pub AverageHighF: Vec<Option<f64>>,
+    pub AverageLowF: Vec<Option<f64>>,
+    pub SunshineHours: Option<Vec<Option<f64>>>,
+    pub Country: Option<String>,
+    #[serde(default)]
+    pub CountrySource: Option<CountrySource>,
+    pub ISOCode: Option<String>,
+    /// ISO 3166-1 alpha-3 code, e.g. "AUS".
+    #[serde(default)]
+    pub ISOAlpha3: Option<String>,
+    /// ISO 3166-1 numeric code, e.g. "036".
+    #[serde(default)]
+    pub ISONumeric: Option<String>,
+    /// The state or province, e.g. "Western Australia".
+    #[serde(default)]
+    pub Admin1: Option<String>,
+    /// The state or province's ISO 3166-2 code, e.g. "AU-WA".
+    #[serde(default)]
+    pub Admin1ISOCode: Option<String>,
+    /// The county or district, where geonames has one.
+    #[serde(default)]
+    pub Admin2: Option<String>,
+    pub Continent: Option<String>,
+    /// The matched geonames city, see http://www.geonames.org/
+    #[serde(default)]
+    pub GeonameID: Option<u32>,
+    /// In decimal degrees, from the matched geonames city, or else the
+    /// Wikipedia page.
+    #[serde(default)]
+    pub Latitude: Option<f64>,
+    #[serde(default)]
+    pub Longitude: Option<f64>,
+    #[serde(default)]
+    pub Population: Option<u64>,
+    /// In metres.
+    #[serde(default)]
+    pub Elevation: Option<i32>,
+    /// IANA time zone, e.g. "Australia/Perth".
+    #[serde(default)]
+    pub Timezone: Option<String>,
+}
+
+/// How a location's country was found.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CountrySource {
+    /// A geonames city with the location's name.
+    Geonames,
+    /// The country row of the infobox on the location's Wikipedia page.
+    Infobox,
+    Unresolved,
+}
@@ -0,0 +1,196 @@
+use rusqlite;
+use rusqlite::types::{ToSql, ToSqlOutput};
+use serde_json::Value;
+
+#[derive(Debug, PartialEq)]
+pub enum FetchResult {
+    Page,
+    Duplicate,
+    FetchError,
+    StatusError,
+}
+
+impl ToSql for FetchResult {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match &self {
+            FetchResult::Page => Ok(ToSqlOutput::from("Page")),
+            FetchResult::Duplicate => Ok(ToSqlOutput::from("Duplicate")),
+            FetchResult::FetchError => Ok(ToSqlOutput::from("FetchError")),
+            FetchResult::StatusError => Ok(ToSqlOutput::from("StatusError")),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TemperatureTableType {
+    Regular,
+    Irregular,
+    Infobox,
+}
+
+impl ToSql for TemperatureTableType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match &self {
+            TemperatureTableType::Regular => Ok(ToSqlOutput::from("Regular")),
+            TemperatureTableType::Irregular => Ok(ToSqlOutput::from("Irregular")),
+            TemperatureTableType::Infobox => Ok(ToSqlOutput::from("Infobox")),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseResult {
+    Parsed,
+    ParseError,
+    NoValidTablesFound,
+}
+
+impl ToSql for ParseResult {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match &self {
+            ParseResult::Parsed => Ok(ToSqlOutput::from("Parsed")),
+            ParseResult::ParseError => Ok(ToSqlOutput::from("ParseError")),
+            ParseResult::NoValidTablesFound => Ok(ToSqlOutput::from("NoValidTablesFound")),
+        }
+    }
+}
+
+/// How the monthly sunshine hours series was derived from the table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SunshineConversion {
+    Monthly,
+    DailyTimesDaysInMonth,
+    PercentPossibleTimesDaylight,
+}
+
+impl ToSql for SunshineConversion {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match &self {
+            SunshineConversion::Monthly => Ok(ToSqlOutput::from("Monthly")),
+            SunshineConversion::DailyTimesDaysInMonth => {
+                Ok(ToSqlOutput::from("DailyTimesDaysInMonth"))
+            }
+            SunshineConversion::PercentPossibleTimesDaylight => {
+                Ok(ToSqlOutput::from("PercentPossibleTimesDaylight"))
+            }
+        }
+    }
+}
+
+/// A single month's value as it appears in a climate table cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellValue {
+    Number(f64),
+    Missing,
+    Trace,
+    LessThan(f64),
+}
+
+impl CellValue {
+    /// The value to store in a month series. Trace amounts count as zero,
+    /// "less than" values as their upper bound and missing months as `None`
+    /// (serialized as `null`).
+    pub fn to_float(&self) -> Option<f64> {
+        match *self {
+            CellValue::Number(value) => Some(value),
+            CellValue::Missing => None,
+            CellValue::Trace => Some(0.0),
+            CellValue::LessThan(value) => Some(value),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PageResult {
+    pub page_name: String,
+    pub fetch_result: FetchResult,
+    pub response_url: Option<String>,
+    pub status_code: Option<u16>,
+    pub content_location_url: Option<String>,
+    pub page_id: Option<u32>,
+    pub wikipedia_url: Option<String>,
+    pub location_name: Option<String>,
+    /// Coordinates of the location in decimal degrees, north and east positive.
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub table_html: Option<String>,
+    /// Heading of the page section the table is in, e.g. "Climate". None for
+    /// a table in the lead.
+    pub section: Option<String>,
+    pub temperature_table_type: Option<TemperatureTableType>,
+    pub average_high_c: Option<Value>,
+    pub average_low_c: Option<Value>,
+    pub average_high_f: Option<Value>,
+    pub average_low_f: Option<Value>,
+    pub sunshine_hours: Option<Value>,
+    pub percent_possible_sunshine: Option<Value>,
+    pub daylight_hours: Option<Value>,
+    pub sunshine_conversion: Option<SunshineConversion>,
+    pub parse_result: Option<ParseResult>,
+}
+
+impl Default for PageResult {
+    fn default() -> PageResult {
+        PageResult {
+            page_name: String::new(),
+            fetch_result: FetchResult::FetchError,
+            response_url: None,
+            status_code: None,
+            content_location_url: None,
+            page_id: None,
+            wikipedia_url: None,
+            location_name: None,
+            latitude: None,
+            longitude: None,
+            table_html: None,
+            section: None,
+            temperature_table_type: None,
+            average_high_c: None,
+            average_low_c: None,
+            average_high_f: None,
+            average_low_f: None,
+            sunshine_hours: None,
+            percent_possible_sunshine: None,
+            daylight_hours: None,
+            sunshine_conversion: None,
+            parse_result: None,
+        }
+    }
+}
+
+impl PageResult {
+    /// Keys identifying the Wikipedia page that was actually fetched, i.e. after
+    /// redirects, so that different titles for the same page can be detected.
+    pub fn canonical_keys(&self) -> Vec<String> {
+        let mut keys = Vec::new();
+
+        if let Some(page_id) = self.page_id {
+            keys.push(format!("page_id:{}", page_id));
+        }
+        if let Some(ref url) = self.content_location_url {
+            keys.push(format!("url:{}", url));
+        }
+
+        keys
+    }
+}
+
+#[derive(Debug)]
+pub struct TableRows {
+    pub average_high_c: Option<Value>,
+    pub average_low_c: Option<Value>,
+    pub average_high_f: Option<Value>,
+    pub average_low_f: Option<Value>,
+    pub sunshine_hours: Option<Value>,
+    pub percent_possible_sunshine: Option<Value>,
+    pub daylight_hours: Option<Value>,
+    pub sunshine_conversion: Option<SunshineConversion>,
+}
+
+#[derive(Debug)]
+pub struct InfoboxRows {
+    pub average_high_c: Option<Value>,
+    pub average_low_c: Option<Value>,
+    pub average_high_f: Option<Value>,
+    pub average_low_f: Option<Value>,
+}
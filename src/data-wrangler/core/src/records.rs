@@ -0,0 +1,79 @@
+//! Reading the rows of the comma separated input files, the way the
+//! geonames crate reads tab separated ones.
+
+use csv::ReaderBuilder;
+pub use geonames::records::{parse_field, read_tsv, Malformed, Records};
+use std::iter::FromIterator;
+
+/// Reads comma separated rows with at least `columns` columns, the way
+/// `read_tsv` does. Fields can be quoted, e.g. `"Korea, Republic of"`.
+pub fn read_csv<T, C, F>(contents: &str, columns: usize, parse: F) -> Records<C>
+where
+    C: FromIterator<T>,
+    F: Fn(&[&str]) -> Result<T, String>,
+{
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .from_reader(contents.as_bytes());
+
+    let mut malformed = Vec::new();
+    let rows = reader
+        .records()
+        .filter_map(|record| {
+            let record = match record {
+                Ok(record) => record,
+                Err(err) => {
+                    malformed.push(Malformed {
+                        line: err
+                            .position()
+                            .map_or(0, |position| position.line() as usize),
+                        reason: err.to_string(),
+                    });
+                    return None;
+                }
+            };
+            let line = record
+                .position()
+                .map_or(0, |position| position.line() as usize);
+            let cols: Vec<&str> = record.iter().map(str::trim).collect();
+            let row = if cols.len() < columns {
+                Err(format!(
+                    "expected at least {} columns, got {}",
+                    columns,
+                    cols.len()
+                ))
+            } else {
+                parse(&cols)
+            };
+            row.map_err(|reason| malformed.push(Malformed { line, reason }))
+                .ok()
+        })
+        .collect();
+
+    Records { rows, malformed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_quoted_csv_fields() {
+        let records: Records<Vec<(String, String)>> = read_csv(
+            "# code,name\nKR,\"Korea, Republic of\"\nXX\nEU, Europe\n",
+            2,
+            |cols| Ok((cols[0].to_string(), cols[1].to_string())),
+        );
+        assert_eq!(
+            records.rows,
+            vec![
+                ("KR".to_string(), "Korea, Republic of".to_string()),
+                ("EU".to_string(), "Europe".to_string()),
+            ]
+        );
+        assert_eq!(records.malformed.len(), 1);
+        assert_eq!(records.malformed[0].line, 3);
+    }
+}
@@ -0,0 +1,96 @@
+//! URLs of pages on a Wikipedia and its RESTBase API.
+
+/// The Wikipedia pages are fetched from: a language edition, e.g. German
+/// Wikipedia, at its own address or another one, e.g. a mock server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wiki {
+    /// Scheme and host, e.g. "https://de.wikipedia.org".
+    pub base_url: String,
+    /// Language code of the edition, e.g. "de".
+    pub language: String,
+}
+
+impl Wiki {
+    /// The Wikipedia in a language, e.g. "de" for https://de.wikipedia.org.
+    pub fn new(language: &str) -> Wiki {
+        Wiki::with_base_url(language, &format!("https://{}.wikipedia.org", language))
+    }
+
+    /// The Wikipedia in a language at another address, e.g.
+    /// "http://127.0.0.1:8080".
+    pub fn with_base_url(language: &str, base_url: &str) -> Wiki {
+        Wiki {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            language: language.to_string(),
+        }
+    }
+
+    /// URL of a page's HTML from the RESTBase API, following redirects.
+    pub fn restbase_url(&self, page: &str) -> String {
+        format!("{}?redirect=true", self.content_location_url(page))
+    }
+
+    /// URL of a page's HTML from the RESTBase API, as given in the
+    /// Content-Location header once redirects are followed.
+    pub fn content_location_url(&self, page: &str) -> String {
+        format!(
+            "{}/api/rest_v1/page/html/{}",
+            self.base_url,
+            page.replace(" ", "_")
+        )
+    }
+
+    /// URL of a page's sections as JSON from the RESTBase API, following
+    /// redirects.
+    pub fn mobile_sections_url(&self, page: &str) -> String {
+        format!(
+            "{}/api/rest_v1/page/mobile-sections/{}?redirect=true",
+            self.base_url,
+            page.replace(" ", "_")
+        )
+    }
+
+    /// URL of the article for a RESTBase URL, e.g. a Content-Location URL.
+    pub fn wikipedia_url(&self, url: &str) -> String {
+        format!("{}/wiki/{}", self.base_url, page_title(url))
+    }
+}
+
+impl Default for Wiki {
+    /// English Wikipedia.
+    fn default() -> Wiki {
+        Wiki::new("en")
+    }
+}
+
+/// The title of the page a RESTBase URL is for, as it is in the URL, e.g.
+/// "Climate_of_Perth" for ".../page/html/Climate_of_Perth?redirect=true".
+pub fn page_title(url: &str) -> &str {
+    let path = url.split('?').next().unwrap_or_default();
+    path.rsplit('/').next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_urls_for_a_wiki() {
+        let wiki = Wiki::default();
+        assert_eq!(
+            wiki.restbase_url("Climate of Perth"),
+            "https://en.wikipedia.org/api/rest_v1/page/html/Climate_of_Perth?redirect=true"
+        );
+        assert_eq!(
+            wiki.wikipedia_url(&wiki.mobile_sections_url("Perth")),
+            "https://en.wikipedia.org/wiki/Perth"
+        );
+
+        let wiki = Wiki::with_base_url("de", "http://127.0.0.1:8080/");
+        assert_eq!(
+            wiki.content_location_url("Klima in Berlin"),
+            "http://127.0.0.1:8080/api/rest_v1/page/html/Klima_in_Berlin"
+        );
+        assert_eq!(Wiki::new("fr").base_url, "https://fr.wikipedia.org");
+    }
+}
@@ -0,0 +1,24 @@
+use regex::Regex;
+use scraper::element_ref::ElementRef;
+use scraper::Selector;
+
+lazy_static! {
+    pub static ref FOOTNOTE: Regex = Regex::new(r"\[[^\]]*\]").unwrap();
+}
+
+/// The text of each row of a table, one entry per non-empty text node.
+pub fn table_data(table: ElementRef<'_>) -> Vec<Vec<&str>> {
+    let row_selector = Selector::parse("tr").unwrap();
+
+    let rows = table.select(&row_selector);
+    rows.map(|row| {
+        row.text()
+            .map(|cell| cell.trim())
+            .filter(|cell| !cell.is_empty())
+            // Footnote markers, e.g. "[a]", are separate text nodes in a <sup>
+            // and would otherwise shift the month columns:
+            .filter(|cell| !FOOTNOTE.replace_all(cell, "").trim().is_empty())
+            .collect::<Vec<_>>()
+    })
+    .collect::<Vec<_>>()
+}
@@ -1,74 +1,142 @@
+extern crate geonames;
 extern crate rayon;
-extern crate regex;
 
+use geonames::{stream_alternate_names, stream_cities};
 use rayon::prelude::*;
-// use regex::RegexBuilder;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::error::Error;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 
-pub struct Config {
-    pub geonames_file: String,
-    pub wikipedia_index_file: String,
-    pub output_directory: String,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Match page titles against every name of every city.
+    Names,
+    /// Match cities to the Wikipedia pages they link to in the alternate
+    /// names file, and only cities without a linked page in the
+    /// index by name.
+    Links,
 }
 
-impl Config {
-    pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
-        args.next();
-
-        let geonames_file = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a geonames file path."),
-        };
-
-        let wikipedia_index_file = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a wikipedia index file path."),
-        };
+pub struct Config {
+    pub geonames_file: PathBuf,
+    pub wikipedia_index_file: PathBuf,
+    /// Geonames `alternateNamesV2.txt` file. With it, cities match by their
+    /// names and Wikipedia links in `language` instead of every alternate
+    /// name, and never by a former name.
+    pub alternate_names_file: Option<PathBuf>,
+    pub mode: Mode,
+    pub output_directory: PathBuf,
+    /// Language code of the Wikipedia the index file is of, e.g. "de".
+    pub language: String,
+}
 
-        let output_directory = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a output directory path."),
-        };
+/// The field of a city that matched a page's title.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Field {
+    /// A Wikipedia link in the alternate names file.
+    Link,
+    /// The UTF-8 name.
+    Name,
+    AsciiName,
+    /// A name in the Wikipedia's language in the alternate names file.
+    LanguageName,
+    /// A name in the cities file's alternate names column.
+    AlternateName,
+    /// A part of the time zone, e.g. "Perth" in "Australia/Perth".
+    Timezone,
+}
 
-        Ok(Config {
-            geonames_file,
-            wikipedia_index_file,
-            output_directory,
-        })
+impl Field {
+    fn as_str(self) -> &'static str {
+        match self {
+            Field::Link => "link",
+            Field::Name => "name",
+            Field::AsciiName => "asciiname",
+            Field::LanguageName => "language",
+            Field::AlternateName => "alternatename",
+            Field::Timezone => "timezone",
+        }
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<Error>> {
-    let geonames_file =
-        fs::read_to_string(config.geonames_file).expect("Unable to read geonames file");
-    let wikipedia_index_file = fs::read_to_string(config.wikipedia_index_file)
+/// A city, the title of a page about it and the field that matched.
+type PageMatch = (u32, String, Field);
+
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let geonames_file = File::open(&config.geonames_file).expect("Unable to read geonames file");
+    let wikipedia_index_file = fs::read_to_string(&config.wikipedia_index_file)
         .expect("Unable to read wikipedia index file");
-    let out_file_path = format!("{}cross_ref_pages_to_fetch.txt", config.output_directory);
+    let out_file_path = config.output_directory.join("cross_ref_pages_to_fetch.txt");
     let mut out_file = File::create(out_file_path).expect("Could not create file");
+    let mapping_file_path = config
+        .output_directory
+        .join("cross_ref_geonames_to_wikipedia.tsv");
+    let mut mapping_file = File::create(mapping_file_path).expect("Could not create file");
 
-    let cities = extract_cities(geonames_file);
-    // println!("{:?}", cities.contains(""));
+    let mut city_names = extract_cities(BufReader::new(geonames_file), &config.geonames_file)?;
+    match config.alternate_names_file {
+        Some(ref path) => {
+            let alternate_names_file =
+                File::open(path).expect("Unable to read alternate names file");
+            add_alternate_names(
+                BufReader::new(alternate_names_file),
+                path,
+                &config.language,
+                &mut city_names,
+            )?;
+        }
+        None if config.mode == Mode::Links => {
+            return Err(From::from(
+                "Matching by links needs the alternate names file.",
+            ))
+        }
+        None => {}
+    }
 
-    let pages: Vec<_> = wikipedia_index_file
+    // Index lines are "offset:page id:title", and titles can hold a colon.
+    let titles: Vec<&str> = wikipedia_index_file
         .par_lines()
-        .filter_map(|line| {
-            let split_line: Vec<&str> = line.split(':').collect();
-            let page = split_line.last().unwrap().trim().to_string();
-            // println!("Page: {:?}", page);
-
-            if cities.contains(&page) {
-                println!("Match: {:?}", &page);
-                Some(page)
-            } else {
-                None
-            }
-        })
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .map(str::trim)
         .collect();
 
+    let matches = match config.mode {
+        Mode::Names => match_names(city_names.iter(), &titles, true),
+        Mode::Links => match_links(&city_names, &titles),
+    };
+
+    // One row per city and page, with every field that matched, so false
+    // matches, e.g. by time zone, can be found and filtered out.
+    let mut fields_by_match: BTreeMap<(u32, &str), BTreeSet<Field>> = BTreeMap::new();
+    for (geonameid, title, field) in &matches {
+        fields_by_match
+            .entry((*geonameid, title.as_str()))
+            .or_default()
+            .insert(*field);
+    }
+    write!(
+        mapping_file,
+        "GeonameID\tCityName\tCountryCode\tWikipediaTitle\tMatchedFields\r\n"
+    )?;
+    for ((geonameid, title), fields) in &fields_by_match {
+        let city = &city_names[geonameid];
+        let fields: Vec<&str> = fields.iter().map(|field| field.as_str()).collect();
+        write!(
+            mapping_file,
+            "{}\t{}\t{}\t{}\t{}\r\n",
+            geonameid,
+            city.name,
+            city.country_code,
+            title,
+            fields.join(",")
+        )?;
+    }
+
+    let pages: BTreeSet<&str> = matches.iter().map(|(_, title, _)| title.as_str()).collect();
     for page in pages {
         // println!("Page: {:?}", page);
         write!(out_file, "{}\r\n", page).expect("Unable to write to file.");
@@ -77,36 +145,287 @@ pub fn run(config: Config) -> Result<(), Box<Error>> {
     Ok(())
 }
 
-fn extract_cities(geonames_file: String) -> HashSet<String> {
-    let mut cities = HashSet::new();
-
-    for line in geonames_file.lines() {
-        let mut names: Vec<String> = Vec::new();
+/// The cities with a page in the index whose title is one of their names.
+fn match_names<'c, I>(cities: I, titles: &[&str], with_timezones: bool) -> Vec<PageMatch>
+where
+    I: Iterator<Item = (&'c u32, &'c CityNames)>,
+{
+    let mut ids_by_name: HashMap<&str, Vec<(u32, Field)>> = HashMap::new();
+    for (geonameid, city) in cities {
+        for (name, field) in city.page_names(with_timezones) {
+            ids_by_name
+                .entry(name)
+                .or_default()
+                .push((*geonameid, field));
+        }
+    }
 
-        let split_line: Vec<&str> = line.split('\t').collect();
+    titles
+        .par_iter()
+        .filter_map(|title| ids_by_name.get(title).map(|ids| (title, ids)))
+        .flat_map_iter(|(title, ids)| {
+            println!("Match: {:?}", title);
+            ids.iter()
+                .map(move |(geonameid, field)| (*geonameid, title.to_string(), *field))
+        })
+        .collect()
+}
 
-        names.push(split_line[1].trim().to_string()); // utf8 name
-        names.push(split_line[2].trim().to_string()); // ascii name
-        split_line[3] // alternative names list
-            .split(',')
-            .for_each(|name| names.push(name.trim().to_string()));
-        split_line[17] // time zone
-            .split('/')
-            .for_each(|name| names.push(name.trim().to_string()));
+/// The cities' linked pages that are in the index, and name matches for the
+/// cities without one. Time zone parts, e.g. "America", aren't names here.
+fn match_links(cities: &HashMap<u32, CityNames>, titles: &[&str]) -> Vec<PageMatch> {
+    let index: HashSet<&str> = titles.iter().cloned().collect();
 
-        // println!("{:?}", names);
-        for name in names {
-            cities.insert(name);
+    let mut matches = Vec::new();
+    for (geonameid, city) in cities {
+        for title in &city.wikipedia_titles {
+            if index.contains(title.as_str()) {
+                println!("Match -> Link: {:?}", title);
+                matches.push((*geonameid, title.clone(), Field::Link));
+            }
         }
     }
-    cities.remove("");
 
-    cities
+    let linked: HashSet<u32> = matches.iter().map(|(geonameid, _, _)| *geonameid).collect();
+    let unlinked = cities
+        .iter()
+        .filter(|(geonameid, _)| !linked.contains(geonameid));
+    matches.extend(match_names(unlinked, titles, false));
+
+    matches
+}
+
+/// The names a city's Wikipedia page could have.
+#[derive(Debug, Default)]
+struct CityNames {
+    name: String,
+    ascii_name: String,
+    country_code: String,
+    /// The parts of the time zone, e.g. "Australia" and "Perth".
+    timezone_parts: Vec<String>,
+    /// The alternate names column, in every language.
+    alternate_names: Vec<String>,
+    /// The current names and Wikipedia titles in the Wikipedia's language
+    /// from the alternate names file, when it has any for the city.
+    language_names: Option<Vec<String>>,
+    /// The titles of the pages of the Wikipedia the alternate names file
+    /// links to.
+    wikipedia_titles: Vec<String>,
+    historic_names: HashSet<String>,
 }
 
+impl CityNames {
+    /// The names, and the fields they're from.
+    fn page_names(&self, with_timezones: bool) -> Vec<(&str, Field)> {
+        let alternate_names = match self.language_names {
+            Some(ref language_names) => (language_names, Field::LanguageName),
+            None => (&self.alternate_names, Field::AlternateName),
+        };
+        let alternate_names = alternate_names
+            .0
+            .iter()
+            .filter(|name| !self.historic_names.contains(*name))
+            .map(|name| (name, alternate_names.1));
+        let timezone_parts = self
+            .timezone_parts
+            .iter()
+            .filter(|_| with_timezones)
+            .map(|name| (name, Field::Timezone));
+
+        let mut names: Vec<(&str, Field)> = vec![
+            (&self.name, Field::Name),
+            (&self.ascii_name, Field::AsciiName),
+        ]
+        .into_iter()
+        .chain(alternate_names)
+        .chain(timezone_parts)
+        .map(|(name, field)| (name.trim(), field))
+        .filter(|(name, _)| !name.is_empty())
+        .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+fn extract_cities<R: BufRead>(
+    geonames_file: R,
+    path: &Path,
+) -> std::io::Result<HashMap<u32, CityNames>> {
+    let mut cities = HashMap::new();
+
+    stream_cities(geonames_file, |city| match city {
+        Ok(city) => {
+            let names = CityNames {
+                name: city.name.to_string(),
+                ascii_name: city.ascii_name.to_string(),
+                country_code: city.country_code.to_string(),
+                timezone_parts: city.timezone.split('/').map(str::to_string).collect(),
+                alternate_names: city
+                    .alternate_names
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect(),
+                ..Default::default()
+            };
+            cities.insert(city.geonameid, names);
+        }
+        Err(malformed) => println!(
+            "Parse -> Malformed: {}:{}: {}",
+            path.display(),
+            malformed.line,
+            malformed.reason
+        ),
+    })?;
+
+    Ok(cities)
+}
+
+fn add_alternate_names<R: BufRead>(
+    alternate_names_file: R,
+    path: &Path,
+    language: &str,
+    cities: &mut HashMap<u32, CityNames>,
+) -> std::io::Result<()> {
+    stream_alternate_names(
+        alternate_names_file,
+        |alternate_name| match alternate_name {
+            Ok(alternate_name) => {
+                // Most rows are for places other than the cities.
+                let city = match cities.get_mut(&alternate_name.geonameid) {
+                    Some(city) => city,
+                    None => return,
+                };
+                let title = alternate_name.wikipedia_title(language);
+                let language_name = match title {
+                    Some(ref title) => Some(title.clone()),
+                    None if alternate_name.is_current_name_in(language) => {
+                        Some(alternate_name.name.to_string())
+                    }
+                    None => None,
+                };
+
+                if alternate_name.is_historic {
+                    city.historic_names.insert(alternate_name.name.to_string());
+                } else if let Some(name) = language_name {
+                    city.language_names.get_or_insert_with(Vec::new).push(name);
+                }
+                city.wikipedia_titles.extend(title);
+            }
+            Err(malformed) => println!(
+                "Parse -> Malformed: {}:{}: {}",
+                path.display(),
+                malformed.line,
+                malformed.reason
+            ),
+        },
+    )
+}
+
+#[cfg(test)]
 mod test {
-    // use super::*;
+    use super::*;
 
     #[test]
     fn case_senstive() {}
+
+    #[test]
+    fn prefers_language_names_to_historic_ones() {
+        let cities_file = "\
+3143244\tOslo\tOslo\tOsló,Christiania,Kristiania\t59.91\t10.75\tP\tPPLC\tNO\t\t12\t\t\t\t580000\t\t20\tEurope/Oslo\t2020-01-01
+2063523\tPerth\tPerth\tPerth City\t-31.95\t115.86\tP\tPPLA\tAU\t\t08\t\t\t\t1896548\t\t20\tAustralia/Perth\t2020-01-01
+";
+        let alternate_names_file = "\
+1\t3143244\ten\tOslo\t1\t\t\t\t\t
+2\t3143244\t\tChristiania\t\t\t\t1\t1624\t1925
+3\t3143244\tis\tOsló\t\t\t\t\t\t
+4\t3143244\tlink\thttps://en.wikipedia.org/wiki/Oslo_Municipality\t\t\t\t\t\t
+";
+        let path = Path::new("test");
+        let mut cities = extract_cities(cities_file.as_bytes(), path).unwrap();
+        add_alternate_names(alternate_names_file.as_bytes(), path, "en", &mut cities).unwrap();
+
+        let names = |geonameid: u32, with_timezones: bool| -> Vec<&str> {
+            cities[&geonameid]
+                .page_names(with_timezones)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+        let oslo = names(3143244, true);
+        assert!(oslo.contains(&"Oslo Municipality"));
+        assert!(!oslo.contains(&"Christiania"));
+        assert!(!oslo.contains(&"Osló"));
+        // Without names in the language, the cities file's names are used.
+        let perth = names(2063523, true);
+        assert!(perth.contains(&"Perth City"));
+        assert!(perth.contains(&"Australia"));
+        assert!(!names(2063523, false).contains(&"Australia"));
+    }
+
+    #[test]
+    fn matches_links_of_the_wikis_language() {
+        let cities_file = "\
+2950159\tBerlin\tBerlin\t\t52.52\t13.41\tP\tPPLC\tDE\t\t16\t\t\t\t3426354\t\t74\tEurope/Berlin\t2020-01-01
+";
+        let alternate_names_file = "\
+1\t2950159\tlink\thttps://en.wikipedia.org/wiki/Berlin\t\t\t\t\t\t
+2\t2950159\tlink\thttps://de.wikipedia.org/wiki/Berlin_(Stadt)\t\t\t\t\t\t
+";
+        let path = Path::new("test");
+        let mut cities = extract_cities(cities_file.as_bytes(), path).unwrap();
+        add_alternate_names(alternate_names_file.as_bytes(), path, "de", &mut cities).unwrap();
+
+        assert_eq!(
+            match_links(&cities, &["Berlin", "Berlin (Stadt)"]),
+            vec![(2950159, "Berlin (Stadt)".to_string(), Field::Link)]
+        );
+    }
+
+    #[test]
+    fn matches_links_before_names() {
+        let cities_file = "\
+3143244\tOslo\tOslo\t\t59.91\t10.75\tP\tPPLC\tNO\t\t12\t\t\t\t580000\t\t20\tEurope/Oslo\t2020-01-01
+2063523\tPerth\tPerth\t\t-31.95\t115.86\tP\tPPLA\tAU\t\t08\t\t\t\t1896548\t\t20\tAustralia/Perth\t2020-01-01
+";
+        let alternate_names_file = "\
+4\t3143244\tlink\thttps://en.wikipedia.org/wiki/Oslo_Municipality\t\t\t\t\t\t
+";
+        let titles = vec!["Oslo", "Oslo Municipality", "Perth", "Australia", "Europe"];
+        let path = Path::new("test");
+        let mut cities = extract_cities(cities_file.as_bytes(), path).unwrap();
+        add_alternate_names(alternate_names_file.as_bytes(), path, "en", &mut cities).unwrap();
+
+        let mut matches = match_links(&cities, &titles);
+        matches.sort();
+        assert_eq!(
+            matches,
+            vec![
+                (2063523, "Perth".to_string(), Field::Name),
+                (2063523, "Perth".to_string(), Field::AsciiName),
+                (3143244, "Oslo Municipality".to_string(), Field::Link),
+            ]
+        );
+
+        let mut matches = match_names(cities.iter(), &titles, true);
+        matches.sort();
+        let matches: Vec<(&str, &str)> = matches
+            .iter()
+            .map(|(_, title, field)| (title.as_str(), field.as_str()))
+            .collect();
+        assert_eq!(
+            matches,
+            vec![
+                ("Australia", "timezone"),
+                ("Perth", "name"),
+                ("Perth", "asciiname"),
+                ("Perth", "timezone"),
+                ("Europe", "timezone"),
+                ("Oslo", "name"),
+                ("Oslo", "asciiname"),
+                ("Oslo", "timezone"),
+                ("Oslo Municipality", "language"),
+            ]
+        );
+    }
 }
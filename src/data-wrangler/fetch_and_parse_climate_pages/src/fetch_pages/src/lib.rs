@@ -1,48 +1,213 @@
+extern crate city_climes_core;
 #[macro_use]
 extern crate lazy_static;
+extern crate regex;
 extern crate reqwest;
-extern crate types;
+extern crate serde_json;
 
-use reqwest::header::ContentLocation;
-use reqwest::{Client, Response};
-use types::{FetchResult, PageResult};
+use city_climes_core::fixtures::fixture_file;
+use city_climes_core::http::HttpClient;
+use city_climes_core::restbase::{page_title, Wiki};
+use city_climes_core::{FetchResult, PageResult};
+use regex::Regex;
+use reqwest::blocking::Response;
+use reqwest::header::CONTENT_LOCATION;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
 
 lazy_static! {
-    static ref CLIENT: Client = Client::new();
+    static ref CLIMATE: Regex = Regex::new(r"(?i)^climate").unwrap();
+    static ref GEOGRAPHY: Regex = Regex::new(r"(?i)^geography").unwrap();
+    static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
 }
 
-pub fn fetch_page(page: &str) -> (PageResult, Option<String>) {
-    let url = create_restbase_url(&page);
-    let resp = CLIENT.get(&url).send();
-    if resp.is_ok() {
-        let mut fetch_result = resp.unwrap();
-        if fetch_result.status().is_success() {
-            let content_location_url = fetch_result
-                .headers()
-                .get::<ContentLocation>()
-                .unwrap()
-                .to_string();
-            let html = fetch_result.text().unwrap();
-            let pr = make_page_result(&page, Some(fetch_result), FetchResult::Page);
-            println!("Fetch -> Page: {:?}", content_location_url);
-            (pr, Some(html))
-        } else {
-            let pr = make_page_result(&page, Some(fetch_result), FetchResult::FetchError);
-            println!("Fetch -> StatusError: {:?}", url);
+pub fn fetch_page(client: &HttpClient, wiki: &Wiki, page: &str) -> (PageResult, Option<String>) {
+    fetch(client, wiki, page, &wiki.restbase_url(page), Some)
+}
+
+/// Fetches only the sections of a page a climate table is likely to be in,
+/// see `climate_sections_html`, instead of the whole page.
+pub fn fetch_sections(
+    client: &HttpClient,
+    wiki: &Wiki,
+    page: &str,
+) -> (PageResult, Option<String>) {
+    let (pr, html) = fetch(
+        client,
+        wiki,
+        page,
+        &wiki.mobile_sections_url(page),
+        |json| climate_sections_html(&json),
+    );
+    // The URL of the page's HTML, so a page has the same URL however it was
+    // fetched.
+    let pr = PageResult {
+        content_location_url: pr
+            .content_location_url
+            .as_ref()
+            .map(|clu| wiki.content_location_url(page_title(clu))),
+        ..pr
+    };
+    (pr, html)
+}
+
+fn fetch<F>(
+    client: &HttpClient,
+    wiki: &Wiki,
+    page: &str,
+    url: &str,
+    to_html: F,
+) -> (PageResult, Option<String>)
+where
+    F: Fn(String) -> Option<String>,
+{
+    let resp = client.get(url);
+    match resp {
+        Ok(fetch_result) => {
+            if fetch_result.status().is_success() {
+                let pr = make_page_result(wiki, page, Some(&fetch_result), FetchResult::Page);
+                match fetch_result.text().map(to_html) {
+                    Ok(Some(html)) => {
+                        println!("Fetch -> Page: {:?}", &pr.content_location_url);
+                        let pr = PageResult {
+                            page_id: extract_page_id(&html),
+                            ..pr
+                        };
+                        (pr, Some(html))
+                    }
+                    Ok(None) => {
+                        let pr = make_page_result(wiki, page, None, FetchResult::FetchError);
+                        println!("Fetch -> Malformed: {:?}", url);
+                        (pr, None)
+                    }
+                    Err(err) => {
+                        let pr = make_page_result(wiki, page, None, FetchResult::FetchError);
+                        println!("Fetch -> FetchError: {:?}", err);
+                        (pr, None)
+                    }
+                }
+            } else {
+                let pr =
+                    make_page_result(wiki, page, Some(&fetch_result), FetchResult::StatusError);
+                println!("Fetch -> StatusError: {:?}", url);
+                (pr, None)
+            }
+        }
+        Err(err) => {
+            let pr = make_page_result(wiki, page, None, FetchResult::FetchError);
+            println!("Fetch -> FetchError: {:?}", err);
             (pr, None)
         }
-    } else {
-        let pr = make_page_result(&page, None, FetchResult::FetchError);
-        println!("Fetch -> FetchError: {:?}", resp);
-        (pr, None)
     }
 }
 
-fn create_restbase_url(page: &str) -> String {
-    format!(
-        "https://en.wikipedia.org/api/rest_v1/page/html/{}?redirect=true",
-        page.replace(" ", "_")
-    )
+/// The sections of a RESTBase mobile-sections page as HTML, the way the
+/// page's HTML has them: the Climate section and the sections under it, or
+/// else the Geography section and the sections under it, or else the whole
+/// page. The page id and coordinates are kept in the head.
+fn climate_sections_html(json: &str) -> Option<String> {
+    let page: Value = serde_json::from_str(json).ok()?;
+    let lead = page.get("lead")?;
+    let sections = page
+        .get("remaining")
+        .and_then(|remaining| remaining["sections"].as_array())
+        .map(|sections| sections.as_slice())
+        .unwrap_or(&[]);
+
+    let line = |section: &Value| {
+        TAG.replace_all(section["line"].as_str().unwrap_or(""), "")
+            .to_string()
+    };
+    let level = |section: &Value| section["toclevel"].as_u64().unwrap_or(1);
+    let chosen = sections
+        .iter()
+        .position(|section| CLIMATE.is_match(&line(section)))
+        .or_else(|| {
+            sections
+                .iter()
+                .position(|section| GEOGRAPHY.is_match(&line(section)))
+        })
+        .map(|start| {
+            let end = sections[start + 1..]
+                .iter()
+                .position(|section| level(section) <= level(&sections[start]))
+                .map_or(sections.len(), |end| start + 1 + end);
+            &sections[start..end]
+        });
+
+    let mut html = String::from("<html><head>");
+    if let Some(id) = lead["id"].as_u64() {
+        html.push_str(&format!(r#"<meta property="mw:pageId" content="{}"/>"#, id));
+    }
+    if let (Some(latitude), Some(longitude)) = (
+        lead["geo"]["latitude"].as_f64(),
+        lead["geo"]["longitude"].as_f64(),
+    ) {
+        html.push_str(&format!(
+            r#"<meta name="geo.position" content="{};{}"/>"#,
+            latitude, longitude
+        ));
+    }
+    html.push_str("</head><body>");
+    if chosen.is_none() {
+        for section in lead["sections"]
+            .as_array()
+            .map_or(&[][..], |s| s.as_slice())
+        {
+            html.push_str(&format!(
+                "<section>{}</section>",
+                section["text"].as_str().unwrap_or("")
+            ));
+        }
+    }
+    for section in chosen.unwrap_or(sections) {
+        html.push_str(&format!(
+            r#"<section><h{level} id="{anchor}">{line}</h{level}>{text}</section>"#,
+            level = level(section) + 1,
+            anchor = section["anchor"]
+                .as_str()
+                .unwrap_or("")
+                .replace('"', "&quot;"),
+            line = section["line"].as_str().unwrap_or(""),
+            text = section["text"].as_str().unwrap_or(""),
+        ));
+    }
+    html.push_str("</body></html>");
+    Some(html)
+}
+
+/// Reads a page from a directory of saved RESTBase HTML instead of fetching
+/// it, as if it had been fetched without a redirect. A missing file is a 404.
+pub fn read_page(wiki: &Wiki, directory: &Path, page: &str) -> (PageResult, Option<String>) {
+    let content_location_url = wiki.content_location_url(page);
+    match fs::read_to_string(fixture_file(directory, page)) {
+        Ok(html) => {
+            println!("Fetch -> Fixture: {:?}", &content_location_url);
+            let pr = PageResult {
+                page_name: page.to_string(),
+                fetch_result: FetchResult::Page,
+                response_url: Some(wiki.restbase_url(page)),
+                page_id: extract_page_id(&html),
+                wikipedia_url: Some(wiki.wikipedia_url(&content_location_url)),
+                location_name: Some(extract_location_name(&content_location_url)),
+                content_location_url: Some(content_location_url),
+                status_code: Some(200),
+                ..Default::default()
+            };
+            (pr, Some(html))
+        }
+        Err(_) => {
+            println!("Fetch -> StatusError: {:?}", &content_location_url);
+            let pr = PageResult {
+                page_name: page.to_string(),
+                fetch_result: FetchResult::StatusError,
+                status_code: Some(404),
+                ..Default::default()
+            };
+            (pr, None)
+        }
+    }
 }
 
 fn extract_location_name(clu: &str) -> String {
@@ -61,25 +226,36 @@ fn extract_location_name(clu: &str) -> String {
     }
 }
 
-fn create_wikipedia_url(clu: &str) -> String {
-    let restbase_url_section = "api/rest_v1/page/html";
-    let wikipedia_url_section = "wiki";
-    clu.replace(restbase_url_section, wikipedia_url_section)
+fn extract_page_id(html: &str) -> Option<u32> {
+    // RESTBase HTML has the page id in the head, e.g.
+    // <meta property="mw:pageId" content="645042"/>
+    let meta = "property=\"mw:pageId\" content=\"";
+    let start = html.find(meta)? + meta.len();
+    let end = html[start..].find('"')? + start;
+    html[start..end].parse().ok()
 }
 
-fn make_page_result(page: &str, fetch_result: Option<Response>, fr: FetchResult) -> PageResult {
+fn make_page_result(
+    wiki: &Wiki,
+    page: &str,
+    fetch_result: Option<&Response>,
+    fr: FetchResult,
+) -> PageResult {
     match fr {
         FetchResult::Page => {
             let fetch_result = fetch_result.unwrap();
+            let response_url = fetch_result.url().to_string();
+            // Without a Content-Location header the response URL is the best
+            // record of the page that was fetched.
             let content_location_url = fetch_result
                 .headers()
-                .get::<ContentLocation>()
-                .unwrap()
-                .to_string();
-            let response_url = fetch_result.url().to_string();
+                .get(CONTENT_LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+                .unwrap_or_else(|| response_url.clone());
             let status_code = fetch_result.status().as_u16();
             let location_name = extract_location_name(&content_location_url);
-            let wikipedia_url = create_wikipedia_url(&content_location_url);
+            let wikipedia_url = wiki.wikipedia_url(&content_location_url);
             PageResult {
                 page_name: page.to_string(),
                 fetch_result: FetchResult::Page,
@@ -96,6 +272,11 @@ fn make_page_result(page: &str, fetch_result: Option<Response>, fr: FetchResult)
             fetch_result: FetchResult::FetchError,
             ..Default::default()
         },
+        FetchResult::Duplicate => PageResult {
+            page_name: page.to_string(),
+            fetch_result: FetchResult::Duplicate,
+            ..Default::default()
+        },
         FetchResult::StatusError => {
             let fetch_result = fetch_result.unwrap();
             let status_code = fetch_result.status().as_u16();
@@ -109,10 +290,207 @@ fn make_page_result(page: &str, fetch_result: Option<Response>, fr: FetchResult)
     }
 }
 
+#[cfg(test)]
+extern crate tiny_http;
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use std::env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use tiny_http::{Header, Response, ResponseBox, Server};
+
+    const PAGE: &str = r#"<head><meta property="mw:pageId" content="1001"/></head>"#;
+
+    /// A Wikipedia on a local port that answers each request, by its path,
+    /// with `respond`.
+    fn mock_wiki<F>(respond: F) -> Wiki
+    where
+        F: Fn(&str) -> ResponseBox + Send + 'static,
+    {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let address = server.server_addr().to_ip().unwrap();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let response = respond(request.url());
+                let _ = request.respond(response);
+            }
+        });
+        Wiki::with_base_url("en", &format!("http://{}", address))
+    }
+
+    fn header(name: &str, value: &str) -> Header {
+        Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn fetches_pages_from_a_mock_wiki() {
+        let wiki = mock_wiki(|path| match path {
+            "/api/rest_v1/page/html/Climate_of_Perth?redirect=true" => Response::from_string(PAGE)
+                .with_header(header(
+                    "Content-Location",
+                    "https://en.wikipedia.org/api/rest_v1/page/html/Climate_of_Perth",
+                ))
+                .boxed(),
+            "/api/rest_v1/page/html/Climate_of_NYC?redirect=true" => Response::empty(302)
+                .with_header(header(
+                    "Location",
+                    "/api/rest_v1/page/html/Climate_of_New_York_City",
+                ))
+                .boxed(),
+            "/api/rest_v1/page/html/Climate_of_New_York_City" => {
+                Response::from_string(PAGE).boxed()
+            }
+            _ => Response::empty(404).boxed(),
+        });
+        let client = HttpClient::default();
+
+        let (page_result, html) = fetch_page(&client, &wiki, "Climate of Perth");
+        assert!(html.is_some());
+        assert_eq!(page_result.status_code, Some(200));
+        assert_eq!(page_result.page_id, Some(1001));
+        assert_eq!(page_result.location_name, Some("Perth".to_string()));
+        assert_eq!(
+            page_result.content_location_url,
+            Some("https://en.wikipedia.org/api/rest_v1/page/html/Climate_of_Perth".to_string())
+        );
+        assert_eq!(
+            page_result.wikipedia_url,
+            Some(format!("{}/wiki/Climate_of_Perth", wiki.base_url))
+        );
+
+        let (page_result, html) = fetch_page(&client, &wiki, "Climate of NYC");
+        assert!(html.is_some());
+        assert_eq!(
+            page_result.content_location_url,
+            Some(wiki.content_location_url("Climate of New York City"))
+        );
+        assert_eq!(page_result.location_name, Some("New York City".to_string()));
+
+        let (page_result, html) = fetch_page(&client, &wiki, "Climate of Atlantis");
+        assert!(html.is_none());
+        assert_eq!(page_result.fetch_result, FetchResult::StatusError);
+        assert_eq!(page_result.status_code, Some(404));
+    }
+
+    #[test]
+    fn retries_rate_limited_requests() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let wiki = mock_wiki(move |path| {
+            let request = counter.fetch_add(1, Ordering::SeqCst);
+            if path.contains("Climate_of_Perth") && request > 0 {
+                Response::from_string(PAGE).boxed()
+            } else {
+                Response::empty(429)
+                    .with_header(header("Retry-After", "0"))
+                    .boxed()
+            }
+        });
+        let client = HttpClient::default();
+
+        let (page_result, html) = fetch_page(&client, &wiki, "Climate of Perth");
+        assert!(html.is_some());
+        assert_eq!(page_result.status_code, Some(200));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Given up on after the last retry.
+        let (page_result, html) = fetch_page(&client, &wiki, "Climate of Oslo");
+        assert!(html.is_none());
+        assert_eq!(page_result.status_code, Some(429));
+        assert_eq!(
+            requests.load(Ordering::SeqCst),
+            2 + 1 + city_climes_core::http::MAX_RETRIES as usize
+        );
+    }
+
+    #[test]
+    fn times_out_slow_responses() {
+        let wiki = mock_wiki(|_| {
+            thread::sleep(Duration::from_millis(500));
+            Response::from_string(PAGE).boxed()
+        });
+        let client = HttpClient::new(Duration::from_millis(100));
+
+        let (page_result, html) = fetch_page(&client, &wiki, "Climate of Perth");
+        assert!(html.is_none());
+        assert_eq!(page_result.fetch_result, FetchResult::FetchError);
+        assert_eq!(page_result.status_code, None);
+    }
+
     #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+    fn rejects_malformed_sections() {
+        let wiki = mock_wiki(|_| Response::from_string("<html>").boxed());
+        let client = HttpClient::default();
+
+        let (page_result, html) = fetch_sections(&client, &wiki, "Climate of Perth");
+        assert!(html.is_none());
+        assert_eq!(page_result.fetch_result, FetchResult::FetchError);
+    }
+
+    #[test]
+    fn extracts_page_id() {
+        let html = r#"<head><meta property="mw:pageId" content="645042"/></head>"#;
+        assert_eq!(extract_page_id(html), Some(645042));
+        assert_eq!(extract_page_id("<head></head>"), None);
+    }
+
+    #[test]
+    fn keeps_climate_sections() {
+        let json = r#"{
+            "lead": {
+                "id": 645042,
+                "geo": {"latitude": 59.91, "longitude": 10.75},
+                "sections": [{"id": 0, "text": "<p>Oslo is the capital.</p>"}]
+            },
+            "remaining": {"sections": [
+                {"id": 1, "toclevel": 1, "line": "History", "anchor": "History", "text": "<p>Christiania</p>"},
+                {"id": 2, "toclevel": 1, "line": "Geography", "anchor": "Geography", "text": "<p>Fjord</p>"},
+                {"id": 3, "toclevel": 2, "line": "<i>Climate</i>", "anchor": "Climate", "text": "<table></table>"},
+                {"id": 4, "toclevel": 3, "line": "Records", "anchor": "Records", "text": "<p>Hot</p>"},
+                {"id": 5, "toclevel": 1, "line": "Economy", "anchor": "Economy", "text": "<p>Oil</p>"}
+            ]}
+        }"#;
+        let html = climate_sections_html(json).unwrap();
+        assert_eq!(extract_page_id(&html), Some(645042));
+        assert!(html.contains(r#"<meta name="geo.position" content="59.91;10.75"/>"#));
+        assert!(html.contains(r#"<h3 id="Climate"><i>Climate</i></h3><table></table>"#));
+        assert!(html.contains("Records"));
+        for left_out in &["capital", "Christiania", "Fjord", "Oil"] {
+            assert!(!html.contains(left_out), "{}", left_out);
+        }
+
+        let json = r#"{"lead": {"id": 1, "sections": [{"id": 0, "text": "<table></table>"}]}}"#;
+        assert!(climate_sections_html(json)
+            .unwrap()
+            .contains("<table></table>"));
+        assert_eq!(climate_sections_html("<html>"), None);
+    }
+
+    #[test]
+    fn reads_pages_from_fixtures() {
+        let directory = env::temp_dir().join("city_climes_reads_pages_from_fixtures");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("Climate_of_Perth.html"),
+            r#"<head><meta property="mw:pageId" content="1001"/></head>"#,
+        )
+        .unwrap();
+
+        let (page_result, html) = read_page(&Wiki::default(), &directory, "Climate of Perth");
+        assert!(html.is_some());
+        assert_eq!(page_result.page_id, Some(1001));
+        assert_eq!(page_result.location_name, Some("Perth".to_string()));
+        assert_eq!(
+            page_result.wikipedia_url,
+            Some("https://en.wikipedia.org/wiki/Climate_of_Perth".to_string())
+        );
+
+        let (page_result, html) = read_page(&Wiki::default(), &directory, "Climate of Atlantis");
+        assert!(html.is_none());
+        assert_eq!(page_result.status_code, Some(404));
     }
 }
@@ -1,30 +1,119 @@
+extern crate city_climes_core;
 extern crate fetch_pages;
 extern crate parse_pages;
 extern crate rayon;
+#[macro_use]
+extern crate serde_json;
 extern crate sqlite;
-extern crate types;
 
 use rayon::prelude::*;
 use std::error::Error;
+use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::PathBuf;
 
-use fetch_pages::fetch_page;
-use parse_pages::parse_page;
-use sqlite::save_page;
-use types::*;
+use fetch_pages::{fetch_page, fetch_sections, read_page};
+use parse_pages::{parse_page, Labels, DEFAULT_LABELS};
+use sqlite::{create_table, fetched_keys, merge_duplicates, save_page, saved_titles};
 
-pub fn run(config: Config) -> Result<(), Box<Error>> {
+use city_climes_core::http::HttpClient;
+use city_climes_core::restbase::Wiki;
+use city_climes_core::*;
+pub use sqlite::{remove_unlisted_pages, ExportOptions};
+
+pub struct Config {
+    pub filename: PathBuf,
+    pub db_path: PathBuf,
+    /// Read pages from saved HTML files in this directory instead of
+    /// fetching them (see `city_climes_core::fixtures`).
+    pub fixtures_directory: Option<PathBuf>,
+    /// The client to fetch pages with.
+    pub client: HttpClient,
+    /// The Wikipedia to fetch pages from. Tables are parsed with the labels
+    /// of its language.
+    pub wiki: Wiki,
+    /// TOML file of climate table labels by language, instead of
+    /// `parse_pages::DEFAULT_LABELS`.
+    pub labels_file: Option<PathBuf>,
+    /// Fetch only the Climate or Geography sections of pages instead of the
+    /// whole page (see `fetch_pages::fetch_sections`).
+    pub sections_only: bool,
+}
+
+/// Pages are fetched this many at a time, then checked for duplicates in the
+/// order they are listed.
+const FETCH_BATCH_SIZE: usize = 64;
+
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     let mut f = File::open(&config.filename).expect("Input file not found.");
 
     let mut contents = String::new();
     f.read_to_string(&mut contents)?;
 
-    contents.par_lines().for_each(|page| {
-        let fetch_result = fetch_page(page);
-        let parse_result = parse_page(fetch_result);
-        let _ = save_page(&config.db_path, parse_result);
-    });
+    let labels = match config.labels_file {
+        Some(ref path) => Labels::load(&fs::read_to_string(path)?, &config.wiki.language)?,
+        None => Labels::load(DEFAULT_LABELS, &config.wiki.language)?,
+    };
+
+    create_table(&config.db_path);
+
+    // Titles saved in an earlier run, as a page or as an alias of one, aren't
+    // fetched again.
+    let saved = saved_titles(&config.db_path);
+    let pages: Vec<&str> = contents
+        .lines()
+        .filter(|page| {
+            let is_saved = saved.contains(*page);
+            if is_saved {
+                println!("Fetch -> Saved: {:?}", page);
+            }
+            !is_saved
+        })
+        .collect();
+
+    // Different titles can redirect to the same page, e.g. "Climate of NYC"
+    // and "Climate of New York City"; only the first one listed is parsed.
+    let mut fetched = fetched_keys(&config.db_path);
+
+    for batch in pages.chunks(FETCH_BATCH_SIZE) {
+        let pages: Vec<(PageResult, Option<String>)> = batch
+            .par_iter()
+            .map(|page| match config.fixtures_directory {
+                Some(ref directory) => read_page(&config.wiki, directory, page),
+                None if config.sections_only => fetch_sections(&config.client, &config.wiki, page),
+                None => fetch_page(&config.client, &config.wiki, page),
+            })
+            .collect();
+
+        let pages: Vec<(PageResult, Option<String>, bool)> = pages
+            .into_iter()
+            .map(|(page_result, html)| {
+                let keys = page_result.canonical_keys();
+                let is_duplicate = keys.iter().any(|key| fetched.contains(key));
+                fetched.extend(keys);
+                (page_result, html, is_duplicate)
+            })
+            .collect();
+
+        pages
+            .into_par_iter()
+            .for_each(|(page_result, html, is_duplicate)| {
+                if is_duplicate {
+                    println!("Fetch -> Duplicate: {:?}", &page_result.page_name);
+                    let duplicate = PageResult {
+                        fetch_result: FetchResult::Duplicate,
+                        ..page_result
+                    };
+                    save_page(&config.db_path, duplicate);
+                } else {
+                    let parse_result = parse_page((page_result, html), &labels);
+                    save_page(&config.db_path, parse_result);
+                }
+            });
+    }
+
+    merge_duplicates(&config.db_path);
 
     // for page in contents.lines() {
     //     let fetch_result = fetch_page(page);
@@ -34,3 +123,120 @@ pub fn run(config: Config) -> Result<(), Box<Error>> {
 
     Ok(())
 }
+
+pub struct ExportConfig {
+    pub db_path: PathBuf,
+    pub output_directory: PathBuf,
+    pub options: ExportOptions,
+    /// Database of the IDs given to locations in earlier exports (see
+    /// `sqlite::assign_ids`). Without one, IDs are Wikipedia page ids.
+    pub registry_path: Option<PathBuf>,
+    /// Indent the JSON instead of writing it on one line.
+    pub pretty: bool,
+}
+
+/// Writes the parsed locations in the database to `CityClimes.json`, the
+/// input of the country stages, and with a registry, the locations added and
+/// removed since the last export to `CityClimesChanges.json`.
+pub fn export(config: ExportConfig) -> Result<(), Box<dyn Error>> {
+    let mut locations = sqlite::export(&config.db_path, &config.options)?;
+
+    if let Some(ref registry_path) = config.registry_path {
+        let changes = sqlite::assign_ids(registry_path, &mut locations)?;
+        println!(
+            "Export -> Added: {:?}, Removed: {:?}",
+            changes.added.len(),
+            changes.removed.len()
+        );
+
+        let changes_file_path = config.output_directory.join("CityClimesChanges.json");
+        let mut changes_file = File::create(changes_file_path).expect("Could not create file");
+        write!(changes_file, "{}", serde_json::to_string_pretty(&changes)?)
+            .expect("Unable to write to file.");
+    }
+
+    let out_file_path = config.output_directory.join("CityClimes.json");
+    let mut out_file = File::create(out_file_path).expect("Could not create file");
+
+    println!("Export -> Locations: {:?}", locations.len());
+    if config.pretty {
+        write!(out_file, "{}", serde_json::to_string_pretty(&locations)?)
+            .expect("Unable to write to file.");
+    } else {
+        write!(out_file, "{}", json!(locations)).expect("Unable to write to file.");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rusqlite;
+
+    use self::rusqlite::Connection;
+    use super::*;
+    use std::env;
+
+    fn saved_rows(db_path: &PathBuf) -> Vec<(String, String)> {
+        let conn = Connection::open(db_path).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT PageName, FetchResult FROM FetchAndParseResults ORDER BY PageName")
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        rows.map(|row| row.unwrap()).collect()
+    }
+
+    #[test]
+    fn fetches_each_title_once_across_runs() {
+        let directory = env::temp_dir().join("city_climes_fetches_each_title_once_across_runs");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let page = r#"<html><head><meta property="mw:pageId" content="645042"/></head></html>"#;
+        fs::write(directory.join("Climate_of_NYC.html"), page).unwrap();
+        fs::write(directory.join("Climate_of_New_York_City.html"), page).unwrap();
+        let filename = directory.join("pages_to_fetch.txt");
+        fs::write(
+            &filename,
+            "Climate of NYC\nClimate of New York City\nPerth\n",
+        )
+        .unwrap();
+        let config = || Config {
+            filename: filename.clone(),
+            db_path: directory.join("city_climes.db"),
+            fixtures_directory: Some(directory.clone()),
+            client: HttpClient::default(),
+            wiki: Wiki::default(),
+            labels_file: None,
+            sections_only: false,
+        };
+
+        // The first title listed of a redirect group is the one kept.
+        run(config()).unwrap();
+        assert_eq!(
+            saved_rows(&config().db_path),
+            vec![
+                ("Climate of NYC".to_string(), "Page".to_string()),
+                ("Perth".to_string(), "StatusError".to_string()),
+            ]
+        );
+
+        // Saved titles aren't read again, and a failed one is replaced.
+        fs::remove_file(directory.join("Climate_of_NYC.html")).unwrap();
+        fs::remove_file(directory.join("Climate_of_New_York_City.html")).unwrap();
+        fs::write(
+            directory.join("Perth.html"),
+            r#"<html><head><meta property="mw:pageId" content="1002"/></head></html>"#,
+        )
+        .unwrap();
+        run(config()).unwrap();
+        assert_eq!(
+            saved_rows(&config().db_path),
+            vec![
+                ("Climate of NYC".to_string(), "Page".to_string()),
+                ("Perth".to_string(), "Page".to_string()),
+            ]
+        );
+    }
+}
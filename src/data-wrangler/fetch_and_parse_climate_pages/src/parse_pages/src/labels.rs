@@ -0,0 +1,157 @@
+//! The labels of climate tables in each language's Wikipedia, e.g.
+//! "Average high °C (°F)" in English and "Mittl. Tagesmax. (°C)" in German.
+
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// The labels of the English, German, French, Spanish and Italian Wikipedias.
+pub const DEFAULT_LABELS: &str = include_str!("../labels.toml");
+
+/// A language's labels as they are in a labels file.
+#[derive(Debug, Deserialize)]
+struct LabelPatterns {
+    months: Vec<String>,
+    month_header: Option<String>,
+    average: Option<String>,
+    average_high: String,
+    average_low: String,
+    sunshine: String,
+    percent_possible_sunshine: Option<String>,
+    daylight: Option<String>,
+    fahrenheit: Option<String>,
+    monthly: String,
+    daily: String,
+    #[serde(default)]
+    celsius_only: bool,
+    #[serde(default)]
+    decimal_comma: bool,
+}
+
+/// The labels of one language's climate tables.
+#[derive(Debug, Clone)]
+pub struct Labels {
+    /// Month column headers, January first.
+    pub months: Vec<Regex>,
+    /// The header of the months row, e.g. "Month". Some languages have none.
+    pub month_header: Option<Regex>,
+    /// The first cell of the months row of irregular tables.
+    pub average: Option<Regex>,
+    pub average_high: Regex,
+    pub average_low: Regex,
+    pub sunshine: Regex,
+    pub percent_possible_sunshine: Option<Regex>,
+    pub daylight: Option<Regex>,
+    /// Marks rows with Fahrenheit values outside the parentheses.
+    pub fahrenheit: Option<Regex>,
+    /// Marks sunshine rows of hours per month and per day.
+    pub monthly: Regex,
+    pub daily: Regex,
+    /// Temperatures are only in Celsius; Fahrenheit ones are converted.
+    pub celsius_only: bool,
+    /// Numbers have a decimal comma, e.g. "12,3", instead of a decimal point.
+    pub decimal_comma: bool,
+}
+
+impl Labels {
+    /// A language's labels from a TOML labels file, see `labels.toml`.
+    pub fn load(contents: &str, language: &str) -> Result<Labels, Box<dyn Error>> {
+        let mut languages: HashMap<String, LabelPatterns> = toml::from_str(contents)?;
+        let patterns = languages.remove(language).ok_or(format!(
+            "No climate table labels for language {:?}.",
+            language
+        ))?;
+
+        if patterns.months.len() != 12 {
+            return Err(From::from(format!(
+                "Expected 12 months for language {:?}, got {}.",
+                language,
+                patterns.months.len()
+            )));
+        }
+
+        Ok(Labels {
+            months: patterns
+                .months
+                .iter()
+                .map(|month| whole_cell(month))
+                .collect::<Result<_, _>>()?,
+            month_header: patterns
+                .month_header
+                .as_deref()
+                .map(whole_cell)
+                .transpose()?,
+            average: patterns.average.as_deref().map(whole_cell).transpose()?,
+            average_high: Regex::new(&patterns.average_high)?,
+            average_low: Regex::new(&patterns.average_low)?,
+            sunshine: Regex::new(&patterns.sunshine)?,
+            percent_possible_sunshine: patterns
+                .percent_possible_sunshine
+                .as_deref()
+                .map(Regex::new)
+                .transpose()?,
+            daylight: patterns.daylight.as_deref().map(Regex::new).transpose()?,
+            fahrenheit: patterns.fahrenheit.as_deref().map(Regex::new).transpose()?,
+            monthly: Regex::new(&patterns.monthly)?,
+            daily: Regex::new(&patterns.daily)?,
+            celsius_only: patterns.celsius_only,
+            decimal_comma: patterns.decimal_comma,
+        })
+    }
+
+    /// The labels of English Wikipedia.
+    pub fn english() -> Labels {
+        Labels::load(DEFAULT_LABELS, "en").expect("English labels are valid.")
+    }
+
+    /// The position of a month in a row, e.g. 1 for January in
+    /// ["Month", "Jan", ...].
+    pub fn position(&self, row: &[&str], month: usize) -> Option<usize> {
+        row.iter()
+            .position(|cell| self.months[month].is_match(cell))
+    }
+
+    /// Whether a row is the months row of a table, i.e. has January,
+    /// February, May and December.
+    pub fn is_months_row(&self, row: &[&str]) -> bool {
+        [0, 1, 4, 11]
+            .iter()
+            .all(|&month| self.position(row, month).is_some())
+    }
+}
+
+/// A case insensitive pattern that has to match a whole cell.
+fn whole_cell(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(&format!("^(?:{})$", pattern))
+        .case_insensitive(true)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_every_language() {
+        for language in &["en", "de", "fr", "es", "it"] {
+            assert!(
+                Labels::load(DEFAULT_LABELS, language).is_ok(),
+                "{}",
+                language
+            );
+        }
+        assert!(Labels::load(DEFAULT_LABELS, "xx").is_err());
+
+        let labels = Labels::load(DEFAULT_LABELS, "fr").unwrap();
+        let row = vec![
+            "Mois", "janv.", "févr.", "mars", "avril", "mai", "juin", "juil.", "août", "sept.",
+            "oct.", "nov.", "déc.", "année",
+        ];
+        assert!(labels.is_months_row(&row));
+        assert_eq!(labels.position(&row, 11), Some(12));
+        assert!(labels.month_header.unwrap().is_match("Mois"));
+        assert!(labels
+            .average_high
+            .is_match("Température maximale moyenne (°C)"));
+    }
+}
@@ -1,91 +1,124 @@
+extern crate city_climes_core;
 #[macro_use]
 extern crate lazy_static;
 extern crate regex;
 extern crate scraper;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
-extern crate types;
+extern crate toml;
+
+mod labels;
+
+pub use labels::{Labels, DEFAULT_LABELS};
 
+use city_climes_core::{
+    table_data, CellValue, InfoboxRows, PageResult, ParseResult, SunshineConversion, TableRows,
+    TemperatureTableType, FOOTNOTE,
+};
 use regex::Regex;
-use scraper::element_ref::ElementRef;
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
 use serde_json::Value;
 use std::num::ParseFloatError;
-use types::{InfoboxRows, PageResult, ParseResult, TableRows, TemperatureTableType};
 
 type HasSunshineHours = bool;
+type Table<'a> = Vec<Vec<&'a str>>;
+type Section = Option<String>;
+/// A table with the heading of the section it is in, and maybe its HTML.
+type TableInSection<'a> = (Table<'a>, Section);
+type TableHtmlInSection<'a> = (Table<'a>, Option<String>, Section);
 
 lazy_static! {
-    static ref SUNSHINE: Regex = Regex::new(r"(?i)sunshine hours").unwrap();
-    static ref AVERAGE_HIGH: Regex = Regex::new(r"(?i)(^average high|^high temperature)").unwrap();
-    static ref AVERAGE_LOW: Regex = Regex::new(r"(?i)(^average low|^low temperature)").unwrap();
-    static ref FAHRENHEIT: Regex = Regex::new(r"(?i)\(.+F\)").unwrap();
     static ref IMPERIAL: Regex = Regex::new(r"(?i)^imperial").unwrap();
-    static ref MONTH: Regex = Regex::new(r"(?i)month").unwrap();
-    static ref DAILY: Regex = Regex::new(r"(?i)daily").unwrap();
+    static ref MISSING: Regex = Regex::new(r"(?i)^(|-|‐|–|—|―|n/?a|none)$").unwrap();
+    static ref TRACE: Regex = Regex::new(r"(?i)^(trace|tr)$").unwrap();
 }
 
-pub fn parse_page(page: (PageResult, Option<String>)) -> PageResult {
+/// Parses a page's climate table, labelled as in `labels`, the labels of the
+/// page's language.
+pub fn parse_page(page: (PageResult, Option<String>), labels: &Labels) -> PageResult {
     let (page_result, html) = page;
-    if html.is_none() {
-        page_result
-    } else {
-        let doc = Html::parse_document(&html.unwrap());
-        let regular_table = extract_regular_temperature_table(&doc);
-        let irregular_table = extract_irregular_temperature_table(&doc);
+    if let Some(html) = html {
+        let doc = Html::parse_document(&html);
+        let coordinates = extract_coordinates(&doc);
+        let latitude = coordinates.map(|(latitude, _)| latitude);
+        let page_result = PageResult {
+            latitude,
+            longitude: coordinates.map(|(_, longitude)| longitude),
+            ..page_result
+        };
+        let regular_table = extract_regular_temperature_table(&doc, labels);
+        let irregular_table = extract_irregular_temperature_table(&doc, labels);
         let infobox = extract_infobox_temperature_table(&doc);
 
         match (regular_table, irregular_table, infobox) {
-            (Some((has_sunshine_hours, (table, table_html))), _, _) => {
-                if let Ok(table_rows) = extract_table_data(has_sunshine_hours, table) {
+            (Some((has_sunshine_hours, (table, table_html, section))), _, _) => {
+                if let Ok(table_rows) =
+                    extract_table_data(has_sunshine_hours, table, latitude, labels)
+                {
                     println!("Parse -> Parsed: {:?}", &page_result.page_name);
                     PageResult {
-                        table_html: table_html,
+                        table_html,
+                        section,
                         temperature_table_type: Some(TemperatureTableType::Regular),
                         average_high_c: table_rows.average_high_c,
                         average_low_c: table_rows.average_low_c,
                         average_high_f: table_rows.average_high_f,
                         average_low_f: table_rows.average_low_f,
                         sunshine_hours: table_rows.sunshine_hours,
+                        percent_possible_sunshine: table_rows.percent_possible_sunshine,
+                        daylight_hours: table_rows.daylight_hours,
+                        sunshine_conversion: table_rows.sunshine_conversion,
                         parse_result: Some(ParseResult::Parsed),
                         ..page_result
                     }
                 } else {
                     println!("Parse -> ParseError: {:?}", &page_result.page_name);
                     PageResult {
-                        table_html: table_html,
+                        table_html,
+                        section,
                         temperature_table_type: Some(TemperatureTableType::Regular),
                         parse_result: Some(ParseResult::ParseError),
                         ..page_result
                     }
                 }
             }
-            (_, Some((has_sunshine_hours, table)), _) => {
-                if let Ok(table_rows) = extract_table_data(has_sunshine_hours, table) {
+            (_, Some((has_sunshine_hours, (table, section))), _) => {
+                if let Ok(table_rows) =
+                    extract_table_data(has_sunshine_hours, table, latitude, labels)
+                {
                     println!("Parse -> Parsed: {:?}", &page_result.page_name);
                     PageResult {
+                        section,
                         temperature_table_type: Some(TemperatureTableType::Irregular),
                         average_high_c: table_rows.average_high_c,
                         average_low_c: table_rows.average_low_c,
                         average_high_f: table_rows.average_high_f,
                         average_low_f: table_rows.average_low_f,
                         sunshine_hours: table_rows.sunshine_hours,
+                        percent_possible_sunshine: table_rows.percent_possible_sunshine,
+                        daylight_hours: table_rows.daylight_hours,
+                        sunshine_conversion: table_rows.sunshine_conversion,
                         parse_result: Some(ParseResult::Parsed),
                         ..page_result
                     }
                 } else {
                     println!("Parse -> ParseError: {:?}", &page_result.page_name);
                     PageResult {
+                        section,
                         temperature_table_type: Some(TemperatureTableType::Irregular),
                         parse_result: Some(ParseResult::ParseError),
                         ..page_result
                     }
                 }
             }
-            (_, _, Some((table1, table2))) => {
-                if let Ok(infobox_rows) = extract_infobox_data(table1, table2) {
+            (_, _, Some((table1, table2, section))) => {
+                if let Ok(infobox_rows) = extract_infobox_data(table1, table2, labels) {
                     println!("Parse -> Parsed: {:?}", &page_result.page_name);
                     PageResult {
+                        section,
                         temperature_table_type: Some(TemperatureTableType::Infobox),
                         average_high_c: infobox_rows.average_high_c,
                         average_low_c: infobox_rows.average_low_c,
@@ -97,6 +130,7 @@ pub fn parse_page(page: (PageResult, Option<String>)) -> PageResult {
                 } else {
                     println!("Parse -> ParseError: {:?}", &page_result.page_name);
                     PageResult {
+                        section,
                         temperature_table_type: Some(TemperatureTableType::Infobox),
                         parse_result: Some(ParseResult::ParseError),
                         ..page_result
@@ -114,37 +148,39 @@ pub fn parse_page(page: (PageResult, Option<String>)) -> PageResult {
                 }
             }
         }
+    } else {
+        page_result
     }
 }
 
-fn extract_regular_temperature_table(
-    doc: &Html,
-) -> Option<(HasSunshineHours, (Vec<Vec<&str>>, Option<String>))> {
+fn extract_regular_temperature_table<'a>(
+    doc: &'a Html,
+    labels: &Labels,
+) -> Option<(HasSunshineHours, TableHtmlInSection<'a>)> {
     let table_selector = Selector::parse("table.wikitable").unwrap();
 
     let tables = doc.select(&table_selector);
 
-    let tables = tables.map(|table| (table_data(table), Some(table.html())));
+    let tables = tables.map(|table| {
+        (
+            table_data(table),
+            Some(table.html()),
+            section_heading(table),
+        )
+    });
 
     let mut tables_with_temperatures = tables
-        .filter(|(table, _)| {
-            table.into_iter().any(|row| {
-                row.contains(&"Month")
-                    && row.contains(&"Jan")
-                    && row.contains(&"Feb")
-                    && row.contains(&"Dec")
+        .filter(|(table, _, _)| {
+            table.iter().any(|row| {
+                labels
+                    .month_header
+                    .as_ref()
+                    .is_none_or(|header| has_cell(row, header))
+                    && labels.is_months_row(row)
             })
         })
-        .filter(|(table, _)| {
-            table
-                .into_iter()
-                .any(|row| row.into_iter().any(|cell| AVERAGE_HIGH.is_match(cell)))
-        })
-        .filter(|(table, _)| {
-            table
-                .into_iter()
-                .any(|row| row.into_iter().any(|cell| AVERAGE_LOW.is_match(cell)))
-        })
+        .filter(|(table, _, _)| table.iter().any(|row| has_cell(row, &labels.average_high)))
+        .filter(|(table, _, _)| table.iter().any(|row| has_cell(row, &labels.average_low)))
         .collect::<Vec<_>>();
 
     // println!("\nTEMPERATURE TABLES: {:?}\n", &tables_with_temperatures);
@@ -152,10 +188,14 @@ fn extract_regular_temperature_table(
     let mut tables_with_sunshine_hours = tables_with_temperatures
         .clone()
         .into_iter()
-        .filter(|(table, _)| {
-            table
-                .into_iter()
-                .any(|row| row.into_iter().any(|cell| SUNSHINE.is_match(cell)))
+        .filter(|(table, _, _)| {
+            table.iter().any(|row| {
+                has_cell(row, &labels.sunshine)
+                    || labels
+                        .percent_possible_sunshine
+                        .as_ref()
+                        .is_some_and(|percent| has_cell(row, percent))
+            })
         })
         .collect::<Vec<_>>();
 
@@ -169,32 +209,29 @@ fn extract_regular_temperature_table(
     }
 }
 
-fn extract_irregular_temperature_table(doc: &Html) -> Option<(HasSunshineHours, Vec<Vec<&str>>)> {
+fn extract_irregular_temperature_table<'a>(
+    doc: &'a Html,
+    labels: &Labels,
+) -> Option<(HasSunshineHours, TableInSection<'a>)> {
+    let average = labels.average.as_ref()?;
     let table_selector = Selector::parse("table.wikitable").unwrap();
 
     let tables = doc.select(&table_selector);
 
-    let tables = tables.map(|table| table_data(table));
+    let tables = tables.map(|table| (table_data(table), section_heading(table)));
 
     let mut tables_with_temperatures = tables
-        .filter(|table| {
-            table.into_iter().any(|row| {
-                row.contains(&"Average")
-                    && row.contains(&"Jan")
-                    && row.contains(&"Feb")
-                    && row.contains(&"Dec")
-            })
+        .filter(|(table, _)| {
+            table
+                .iter()
+                .any(|row| has_cell(row, average) && labels.is_months_row(row))
         })
         .collect::<Vec<_>>();
 
     let tables_with_sunshine_hours = tables_with_temperatures
         .clone()
         .into_iter()
-        .filter(|table| {
-            table
-                .into_iter()
-                .any(|row| row.into_iter().any(|cell| SUNSHINE.is_match(cell)))
-        })
+        .filter(|(table, _)| table.iter().any(|row| has_cell(row, &labels.sunshine)))
         .collect::<Vec<_>>();
 
     match (
@@ -205,33 +242,34 @@ fn extract_irregular_temperature_table(doc: &Html) -> Option<(HasSunshineHours,
         (true, false) => None,
         (false, true) => Some((false, tables_with_temperatures.remove(0))),
         (false, false) => {
-            let mut table = tables_with_temperatures.remove(0);
+            let (mut table, section) = tables_with_temperatures.remove(0);
 
             let mut sunshine_rows = tables_with_sunshine_hours
                 .first()
                 .unwrap()
-                .into_iter()
-                .filter(|row| row.into_iter().any(|cell| SUNSHINE.is_match(cell)))
+                .0
+                .iter()
+                .filter(|row| has_cell(row, &labels.sunshine))
                 .collect::<Vec<_>>();
 
             table.push(sunshine_rows.remove(0).to_vec());
 
-            Some((true, table))
+            Some((true, (table, section)))
         }
     }
 }
 
-fn extract_infobox_temperature_table(doc: &Html) -> Option<(Vec<Vec<&str>>, Vec<Vec<&str>>)> {
+fn extract_infobox_temperature_table(doc: &Html) -> Option<(Table<'_>, Table<'_>, Section)> {
     let table_selector = Selector::parse(".infobox").unwrap();
 
     let tables = doc.select(&table_selector);
 
-    let tables = tables.map(|table| table_data(table));
+    let tables = tables.map(|table| (table_data(table), section_heading(table)));
 
     let mut tables_with_temperatures = tables
-        .filter(|table| {
+        .filter(|(table, _)| {
             table
-                .into_iter()
+                .iter()
                 .any(|row| row == &vec!["J", "F", "M", "A", "M", "J", "J", "A", "S", "O", "N", "D"])
         })
         .collect::<Vec<_>>();
@@ -241,61 +279,104 @@ fn extract_infobox_temperature_table(doc: &Html) -> Option<(Vec<Vec<&str>>, Vec<
         // i.e. 3 infobox tables on wikipedia equals 9 returned results.
         // A high number of results appears on country level pages,
         // e.g. Climate of Brazil; therefore, ignore.
-        return None;
+        None
     } else {
         // Items 1 and 2 are the cleaner tables of metric and imperial data
         // or visa-versa.
-        Some((
-            tables_with_temperatures.remove(1),
-            tables_with_temperatures.remove(1),
-        ))
+        let (metric, section) = tables_with_temperatures.remove(1);
+        let (imperial, _) = tables_with_temperatures.remove(1);
+        Some((metric, imperial, section))
     }
 }
 
-fn table_data(table: ElementRef) -> Vec<Vec<&str>> {
-    let row_selector = Selector::parse("tr").unwrap();
-
-    let rows = table.select(&row_selector);
-    rows.map(|row| {
-        row.text()
-            .map(|cell| cell.trim())
-            .filter(|cell| *cell != "")
-            .collect::<Vec<_>>()
-    }).collect::<Vec<_>>()
-}
-
 fn extract_table_data(
     has_sunshine_hours: HasSunshineHours,
     table: Vec<Vec<&str>>,
+    latitude: Option<f64>,
+    labels: &Labels,
 ) -> Result<TableRows, String> {
     // println!("{} - {:?}", has_sunshine_hours, table);
 
-    let months_row = &table
-        .iter()
-        .filter(|row| {
-            row.contains(&"Jan")
-                && row.contains(&"Feb")
-                && row.contains(&"May")
-                && row.contains(&"Dec")
-        })
-        .next()
-        .unwrap();
+    let months_row = &table.iter().find(|row| labels.is_months_row(row)).unwrap();
 
-    let jan_index: usize = months_row.iter().position(|&month| month == "Jan").unwrap();
-    let dec_index: usize = months_row.iter().position(|&month| month == "Dec").unwrap();
+    let mut jan_index: usize = labels.position(months_row, 0).unwrap();
+    let mut dec_index: usize = labels.position(months_row, 11).unwrap();
+    // Without a header, e.g. in German tables, the months row is a cell
+    // shorter than the labelled rows under it.
+    if jan_index == 0 {
+        jan_index += 1;
+        dec_index += 1;
+    }
 
     // println!("\n\n{:?}", &months_row);
 
-    let average_high_rows = filter_for_rows(&table, &AVERAGE_HIGH);
+    let average_high_rows = filter_for_rows(&table, &labels.average_high);
 
     // println!("\nAverage high rows: {:?}", &average_high_rows);
     // println!("Average high rows length: {:?}", &average_high_rows.len());
 
-    let average_low_rows = filter_for_rows(&table, &AVERAGE_LOW);
+    let average_low_rows = filter_for_rows(&table, &labels.average_low);
 
     // println!("Average low rows: {:?}", &average_low_rows);
     // println!("Average low rows length: {:?}", &average_low_rows.len());
 
+    let temperatures = if labels.celsius_only {
+        celsius_temperatures(
+            &average_high_rows,
+            &average_low_rows,
+            labels,
+            jan_index,
+            dec_index,
+        )?
+    } else {
+        celsius_and_fahrenheit_temperatures(
+            months_row,
+            average_high_rows,
+            average_low_rows,
+            labels,
+            jan_index,
+            dec_index,
+        )?
+    };
+
+    let sunshine = if has_sunshine_hours {
+        extract_sunshine_data(&table, jan_index, dec_index, latitude, labels)
+    } else {
+        SunshineData::default()
+    };
+
+    Ok(TableRows {
+        average_high_c: temperatures.average_high_c,
+        average_low_c: temperatures.average_low_c,
+        average_high_f: temperatures.average_high_f,
+        average_low_f: temperatures.average_low_f,
+        sunshine_hours: sunshine.sunshine_hours.map(|values| json!(values)),
+        percent_possible_sunshine: sunshine
+            .percent_possible_sunshine
+            .map(|values| json!(values)),
+        daylight_hours: sunshine.daylight_hours.map(|values| json!(values)),
+        sunshine_conversion: sunshine.sunshine_conversion,
+    })
+}
+
+/// Average temperatures of each month.
+struct Temperatures {
+    average_high_c: Option<Value>,
+    average_low_c: Option<Value>,
+    average_high_f: Option<Value>,
+    average_low_f: Option<Value>,
+}
+
+/// Temperatures from rows with both Celsius and Fahrenheit values, one in
+/// parentheses, e.g. "Average high °C (°F)", or from a row of each.
+fn celsius_and_fahrenheit_temperatures(
+    months_row: &[&str],
+    average_high_rows: Vec<Vec<&str>>,
+    average_low_rows: Vec<Vec<&str>>,
+    labels: &Labels,
+    jan_index: usize,
+    dec_index: usize,
+) -> Result<Temperatures, String> {
     // Some tables have celsius and fahrenheit values on separate rows instead of
     // using parentheses:
     let average_high_row;
@@ -354,27 +435,6 @@ fn extract_table_data(
     // println!("Average high row: {:?}", &average_high_row);
     // println!("Average low row: {:?}", &average_low_row);
 
-    let sunshine_rows: Vec<Vec<&str>>;
-    let sunshine_row;
-    if has_sunshine_hours {
-        let sun_rows = filter_for_rows(&table, &SUNSHINE);
-        if sun_rows.len() > 1 {
-            sunshine_rows = sun_rows
-                .iter()
-                .filter(|row| row.into_iter().any(|cell| MONTH.is_match(cell)))
-                .cloned()
-                .collect();
-        } else {
-            sunshine_rows = sun_rows;
-        }
-        sunshine_row = Some(&sunshine_rows[0]);
-    // println!("Sun rows: {:?}", &sun_rows);
-    // println!("Sunshine rows: {:?}", &sunshine_rows);
-    // println!("Sunshine row: {:?}", &sunshine_row);
-    } else {
-        sunshine_row = None;
-    }
-
     let label = &average_high_row[0];
     // println!("{:?}", label);
 
@@ -402,12 +462,12 @@ fn extract_table_data(
     }
 
     let (average_high_non_paren_values, average_high_paren_values) =
-        match parse_table_temperatures(average_high_values) {
+        match parse_table_temperatures(average_high_values, labels) {
             Ok(tup) => tup,
             Err(err) => return Err(err.to_string()),
         };
     let (average_low_non_paren_values, average_low_paren_values) =
-        match parse_table_temperatures(average_low_values) {
+        match parse_table_temperatures(average_low_values, labels) {
             Ok(tup) => tup,
             Err(err) => return Err(err.to_string()),
         };
@@ -417,7 +477,11 @@ fn extract_table_data(
     let average_low_c: Option<Value>;
     let average_low_f: Option<Value>;
 
-    if FAHRENHEIT.is_match(label) {
+    if labels
+        .fahrenheit
+        .as_ref()
+        .is_some_and(|fahrenheit| fahrenheit.is_match(label))
+    {
         average_high_c = Some(average_high_non_paren_values);
         average_high_f = Some(average_high_paren_values);
         average_low_c = Some(average_low_non_paren_values);
@@ -435,99 +499,284 @@ fn extract_table_data(
     // );
     // println!("LOW:\nC: {:?}\nF: {:?}\n\n", average_low_c, average_low_f);
 
-    let sunshine_values: Option<Value>;
-    if has_sunshine_hours {
-        let row = sunshine_row.unwrap();
-        // println!("sunshine row: {:?}", &row);
-        if SUNSHINE.is_match(&row[1]) {
-            // Is regular table.
-            // Can have daily rather than monthly sunshine values.
-            sunshine_values = parse_sunshine_values(
-                row[1..][jan_index..=dec_index].to_vec(),
-                DAILY.is_match(&row[0]),
-            ).ok();
-
-        // println!("sunshine vals: {:?}", sunshine_values);
-        } else {
-            // Is irregular table.
-            sunshine_values =
-                parse_sunshine_values(row[jan_index..=dec_index].to_vec(), DAILY.is_match(&row[0]))
-                    .ok();
-        }
-    // println!("{:?}", sunshine_values);
-    } else {
-        sunshine_values = None;
-    }
+    Ok(Temperatures {
+        average_high_c,
+        average_low_c,
+        average_high_f,
+        average_low_f,
+    })
+}
 
-    Ok(TableRows {
-        average_high_c: average_high_c,
-        average_low_c: average_low_c,
-        average_high_f: average_high_f,
-        average_low_f: average_low_f,
-        sunshine_hours: sunshine_values,
+/// Temperatures from rows of Celsius values, e.g. "Mittl. Tagesmax. (°C)",
+/// with the Fahrenheit values converted from them.
+fn celsius_temperatures(
+    average_high_rows: &[Vec<&str>],
+    average_low_rows: &[Vec<&str>],
+    labels: &Labels,
+    jan_index: usize,
+    dec_index: usize,
+) -> Result<Temperatures, String> {
+    let average_high_c = average_high_rows
+        .first()
+        .and_then(|row| month_values(row, &labels.average_high, labels, jan_index, dec_index))
+        .ok_or("Wrong number of values")?;
+    let average_low_c = average_low_rows
+        .first()
+        .and_then(|row| month_values(row, &labels.average_low, labels, jan_index, dec_index))
+        .ok_or("Wrong number of values")?;
+
+    Ok(Temperatures {
+        average_high_f: Some(json!(celsius_to_fahrenheit(&average_high_c))),
+        average_low_f: Some(json!(celsius_to_fahrenheit(&average_low_c))),
+        average_high_c: Some(json!(average_high_c)),
+        average_low_c: Some(json!(average_low_c)),
     })
 }
 
+fn celsius_to_fahrenheit(values: &[Option<f64>]) -> Vec<Option<f64>> {
+    values
+        .iter()
+        .map(|c| c.map(|c| ((c * 9.0 / 5.0 + 32.0) * 10.0).round() / 10.0))
+        .collect()
+}
+
+#[derive(Debug, Default)]
+struct SunshineData {
+    sunshine_hours: Option<Vec<Option<f64>>>,
+    percent_possible_sunshine: Option<Vec<Option<f64>>>,
+    daylight_hours: Option<Vec<Option<f64>>>,
+    sunshine_conversion: Option<SunshineConversion>,
+}
+
+fn extract_sunshine_data(
+    table: &Vec<Vec<&str>>,
+    jan_index: usize,
+    dec_index: usize,
+    latitude: Option<f64>,
+    labels: &Labels,
+) -> SunshineData {
+    let sunshine = &labels.sunshine;
+    let daily = &labels.daily;
+    let sunshine_rows = filter_for_rows(table, sunshine);
+
+    // Some tables have both monthly and daily sunshine rows; prefer monthly.
+    let monthly_row = sunshine_rows
+        .iter()
+        .filter(|row| !daily.is_match(row[0]))
+        .find(|row| has_cell(row, &labels.monthly))
+        .or_else(|| sunshine_rows.iter().find(|row| !daily.is_match(row[0])));
+    let daily_row = sunshine_rows.iter().find(|row| daily.is_match(row[0]));
+
+    let monthly =
+        monthly_row.and_then(|row| month_values(row, sunshine, labels, jan_index, dec_index));
+    let daily = daily_row.and_then(|row| month_values(row, sunshine, labels, jan_index, dec_index));
+    let percent_possible_sunshine = labels.percent_possible_sunshine.as_ref().and_then(|label| {
+        filter_for_rows(table, label)
+            .first()
+            .and_then(|row| month_values(row, label, labels, jan_index, dec_index))
+    });
+    let daylight_hours = labels
+        .daylight
+        .as_ref()
+        .and_then(|label| {
+            filter_for_rows(table, label)
+                .first()
+                .and_then(|row| month_values(row, label, labels, jan_index, dec_index))
+        })
+        .or_else(|| latitude.map(|lat| daylight_hours(lat).into_iter().map(Some).collect()));
+
+    let (sunshine_hours, sunshine_conversion) =
+        match (monthly, daily, &percent_possible_sunshine, &daylight_hours) {
+            (Some(monthly), _, _, _) => (Some(monthly), Some(SunshineConversion::Monthly)),
+            (None, Some(daily), _, _) => (
+                Some(daily_to_monthly(&daily)),
+                Some(SunshineConversion::DailyTimesDaysInMonth),
+            ),
+            (None, None, Some(percent), Some(daylight)) => {
+                let daily = percent
+                    .iter()
+                    .zip(daylight.iter())
+                    .map(|(p, d)| match (p, d) {
+                        (Some(p), Some(d)) => Some(p / 100.0 * d),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                (
+                    Some(daily_to_monthly(&daily)),
+                    Some(SunshineConversion::PercentPossibleTimesDaylight),
+                )
+            }
+            _ => (None, None),
+        };
+
+    SunshineData {
+        sunshine_hours,
+        percent_possible_sunshine,
+        daylight_hours,
+        sunshine_conversion,
+    }
+}
+
+fn month_values(
+    row: &Vec<&str>,
+    label: &Regex,
+    labels: &Labels,
+    jan_index: usize,
+    dec_index: usize,
+) -> Option<Vec<Option<f64>>> {
+    // Regular tables can have the row label split over two cells,
+    // e.g. ["Mean monthly", "sunshine hours", ...].
+    let offset = if row.len() > 1 && label.is_match(row[1]) {
+        1
+    } else {
+        0
+    };
+
+    row.get(jan_index + offset..=dec_index + offset)
+        .and_then(|values| parse_month_values(values.to_vec(), labels).ok())
+}
+
+fn has_cell(row: &[&str], label: &Regex) -> bool {
+    row.iter().any(|cell| label.is_match(cell))
+}
+
 fn filter_for_rows<'a>(table: &'a Vec<Vec<&str>>, regex: &Regex) -> Vec<Vec<&'a str>> {
     table
         .iter()
-        .filter(|row| row.into_iter().any(|cell| regex.is_match(cell)) && row.len() > 10)
+        .filter(|row| row.iter().any(|cell| regex.is_match(cell)) && row.len() > 10)
         .cloned()
         .collect()
 }
 
-fn parse_table_temperatures(values: Vec<&str>) -> Result<(Value, Value), ParseFloatError> {
+fn parse_table_temperatures(
+    values: Vec<&str>,
+    labels: &Labels,
+) -> Result<(Value, Value), ParseFloatError> {
     let mut non_paren_values = Vec::new();
     let mut paren_values = Vec::new();
 
     for (index, value) in values.into_iter().enumerate() {
         if index % 2 == 0 {
-            non_paren_values.push(string_to_float(value)?)
+            non_paren_values.push(string_to_float(value, labels)?)
         } else {
-            paren_values.push(string_to_float(value)?)
+            paren_values.push(string_to_float(value, labels)?)
         }
     }
 
     Ok((json!(non_paren_values), json!(paren_values)))
 }
 
-fn parse_sunshine_values(values: Vec<&str>, is_daily: bool) -> Result<Value, ParseFloatError> {
-    let days_per_month = [
-        31.0, 28.25, 31.0, 30.0, 31.0, 30.0, 31.0, 31.0, 30.0, 31.0, 30.0, 31.0,
-    ];
-    let mut sunshine_values = Vec::new();
+fn parse_month_values(
+    values: Vec<&str>,
+    labels: &Labels,
+) -> Result<Vec<Option<f64>>, ParseFloatError> {
+    let mut month_values = Vec::new();
 
     for value in values {
-        sunshine_values.push(string_to_float(value)?)
+        month_values.push(string_to_float(value, labels)?)
     }
 
-    if is_daily {
-        let sv: Vec<f64> = sunshine_values
-            .iter()
-            .zip(days_per_month.iter())
-            .map(|(s, d)| {
+    Ok(month_values)
+}
+
+fn daily_to_monthly(values: &[Option<f64>]) -> Vec<Option<f64>> {
+    // February uses the mean length over the 400 year Gregorian cycle.
+    let days_per_month = [
+        31.0, 28.2425, 31.0, 30.0, 31.0, 30.0, 31.0, 31.0, 30.0, 31.0, 30.0, 31.0,
+    ];
+
+    values
+        .iter()
+        .zip(days_per_month.iter())
+        .map(|(s, d)| {
+            s.map(|s| {
                 let v = s * d;
-                (v * 100.0).round() / 100.0 // Rounding float to 1 decimal place.
+                (v * 100.0).round() / 100.0 // Rounding float to 2 decimal places.
             })
-            .collect();
+        })
+        .collect()
+}
 
-        Ok(json!(sv))
-    } else {
-        Ok(json!(sunshine_values))
-    }
+/// Mean daily daylight hours for each month at the given latitude, using the
+/// CBM model (Forsythe et al., 1995) with sunrise and sunset taken as the
+/// moment the top of the sun is on the horizon.
+pub fn daylight_hours(latitude: f64) -> Vec<f64> {
+    let days_in_month = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    let latitude = latitude.to_radians();
+    let sun_angle = 0.8333_f64.to_radians();
+
+    let mut day_of_year = 0;
+    days_in_month
+        .iter()
+        .map(|days| {
+            let total: f64 = (1..=*days)
+                .map(|day| {
+                    let j = (day_of_year + day) as f64;
+                    let revolution =
+                        0.2163108 + 2.0 * (0.9671396 * (0.00860 * (j - 186.0)).tan()).atan();
+                    let declination = (0.39795 * revolution.cos()).asin();
+                    let x = (sun_angle.sin() + latitude.sin() * declination.sin())
+                        / (latitude.cos() * declination.cos());
+                    24.0 - (24.0 / std::f64::consts::PI) * x.clamp(-1.0, 1.0).acos()
+                })
+                .sum();
+            day_of_year += days;
+            (total / *days as f64 * 100.0).round() / 100.0
+        })
+        .collect()
+}
+
+fn string_to_float(value: &str, labels: &Labels) -> Result<Option<f64>, ParseFloatError> {
+    parse_cell_value(value, labels).map(|cell| cell.to_float())
 }
 
-fn string_to_float(value: &str) -> Result<f64, ParseFloatError> {
-    value
+fn parse_cell_value(value: &str, labels: &Labels) -> Result<CellValue, ParseFloatError> {
+    let value = FOOTNOTE
+        .replace_all(value, "")
         .replace("(", "")
         .replace(")", "")
         .replace("−", "-") // Replace unicode character 'MINUS SIGN' '−' (U+2212) with '-'.
-        .parse::<f64>()
+        .trim()
+        .to_string();
+
+    if MISSING.is_match(&value) {
+        Ok(CellValue::Missing)
+    } else if TRACE.is_match(&value) {
+        Ok(CellValue::Trace)
+    } else if value.starts_with('<') {
+        Ok(CellValue::LessThan(parse_number(
+            value.trim_start_matches('<').trim(),
+            labels.decimal_comma,
+        )?))
+    } else {
+        Ok(CellValue::Number(parse_number(
+            &value,
+            labels.decimal_comma,
+        )?))
+    }
+}
+
+/// Parses a number written in a language's convention: with a decimal comma
+/// and a point or space between thousands, e.g. "1.234,5" in German, or with
+/// a decimal point and a comma between thousands, e.g. "1,234.5" in English.
+fn parse_number(value: &str, decimal_comma: bool) -> Result<f64, ParseFloatError> {
+    let value: String = if decimal_comma {
+        value
+            .chars()
+            .filter(|c| !matches!(c, '.' | ' ' | '\u{a0}' | '\u{202f}'))
+            .map(|c| if c == ',' { '.' } else { c })
+            .collect()
+    } else {
+        value.replace(",", "")
+    };
+
+    value.parse::<f64>()
 }
 
 fn extract_infobox_data(
     table1: Vec<Vec<&str>>,
     table2: Vec<Vec<&str>>,
+    labels: &Labels,
 ) -> Result<InfoboxRows, String> {
     // println!("{:?}\n{:?}", &table1, &table2);
 
@@ -542,12 +791,12 @@ fn extract_infobox_data(
     }
 
     let (average_high_shown_values, average_low_shown_values) =
-        match parse_infobox_temperatures(table1_values.to_vec()) {
+        match parse_infobox_temperatures(table1_values.to_vec(), labels) {
             Ok(tup) => tup,
             Err(err) => return Err(err.to_string()),
         };
     let (average_high_hidden_values, average_low_hidden_values) =
-        match parse_infobox_temperatures(table2_values.to_vec()) {
+        match parse_infobox_temperatures(table2_values.to_vec(), labels) {
             Ok(tup) => tup,
             Err(err) => return Err(err.to_string()),
         };
@@ -576,14 +825,90 @@ fn extract_infobox_data(
     // println!("LOW:\nC: {:?}\nF: {:?}\n\n", average_low_c, average_low_f);
 
     Ok(InfoboxRows {
-        average_high_c: average_high_c,
-        average_low_c: average_low_c,
-        average_high_f: average_high_f,
-        average_low_f: average_low_f,
+        average_high_c,
+        average_low_c,
+        average_high_f,
+        average_low_f,
     })
 }
 
-fn parse_infobox_temperatures(values: Vec<&str>) -> Result<(Value, Value), ParseFloatError> {
+/// The heading of the innermost page section an element is in, e.g.
+/// "Climate". RESTBase HTML wraps each section, and the sections under it,
+/// in a `<section>` that starts with its heading; the lead has none.
+fn section_heading(element: ElementRef) -> Section {
+    let heading = Selector::parse("h1, h2, h3, h4, h5, h6").unwrap();
+
+    element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .filter(|ancestor| ancestor.value().name() == "section")
+        .find_map(|section| section.select(&heading).next())
+        .map(|heading| heading.text().collect::<String>().trim().to_string())
+        .filter(|heading| !heading.is_empty())
+}
+
+/// The page's coordinates as (latitude, longitude) in decimal degrees, from
+/// the `{{coord}}` template's `span.geo` or `.geo-dec` output, or else a geo
+/// `<meta>` tag.
+fn extract_coordinates(doc: &Html) -> Option<(f64, f64)> {
+    let geo = Selector::parse("span.geo").unwrap();
+    let geo_dec = Selector::parse(".geo-dec").unwrap();
+    let meta = Selector::parse(r#"meta[name="geo.position"], meta[name="ICBM"]"#).unwrap();
+
+    let from_geo = doc
+        .select(&geo)
+        .filter_map(|span| parse_decimal_coordinates(&span.text().collect::<String>()));
+    let from_geo_dec = doc
+        .select(&geo_dec)
+        .filter_map(|span| parse_hemisphere_coordinates(&span.text().collect::<String>()));
+    let from_meta = doc.select(&meta).filter_map(|meta| {
+        meta.value()
+            .attr("content")
+            .and_then(parse_decimal_coordinates)
+    });
+
+    from_geo
+        .chain(from_geo_dec)
+        .chain(from_meta)
+        .find(|&(latitude, longitude)| latitude.abs() <= 90.0 && longitude.abs() <= 180.0)
+}
+
+/// E.g. "-31.95224; 115.8614" or "-31.95224, 115.8614".
+fn parse_decimal_coordinates(value: &str) -> Option<(f64, f64)> {
+    let mut parts = value.split([';', ',']).map(str::trim);
+    let latitude = parts.next()?.replace("−", "-").parse().ok()?;
+    let longitude = parts.next()?.replace("−", "-").parse().ok()?;
+    Some((latitude, longitude))
+}
+
+/// E.g. "31.95224°S 115.8614°E".
+fn parse_hemisphere_coordinates(value: &str) -> Option<(f64, f64)> {
+    let mut latitude = None;
+    let mut longitude = None;
+
+    for part in value.split_whitespace() {
+        let hemisphere = part.chars().last()?;
+        let degrees: f64 = part
+            .trim_end_matches(hemisphere)
+            .trim_end_matches('°')
+            .parse()
+            .ok()?;
+        match hemisphere {
+            'N' => latitude = Some(degrees),
+            'S' => latitude = Some(-degrees),
+            'E' => longitude = Some(degrees),
+            'W' => longitude = Some(-degrees),
+            _ => return None,
+        }
+    }
+
+    latitude.zip(longitude)
+}
+
+fn parse_infobox_temperatures(
+    values: Vec<&str>,
+    labels: &Labels,
+) -> Result<(Value, Value), ParseFloatError> {
     // Values must be 36 in length - 3 values for each month.
     // Every chunk of 3 consists of:
     // [precipitation value, average high value, average low value].
@@ -591,8 +916,8 @@ fn parse_infobox_temperatures(values: Vec<&str>) -> Result<(Value, Value), Parse
     let mut average_low_values = Vec::new();
 
     for chunk in values.chunks(3) {
-        average_high_values.push(string_to_float(chunk[1])?);
-        average_low_values.push(string_to_float(chunk[2])?);
+        average_high_values.push(string_to_float(chunk[1], labels)?);
+        average_low_values.push(string_to_float(chunk[2], labels)?);
     }
 
     Ok((json!(average_high_values), json!(average_low_values)))
@@ -600,8 +925,216 @@ fn parse_infobox_temperatures(values: Vec<&str>) -> Result<(Value, Value), Parse
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_coordinates() {
+        let doc = Html::parse_document(
+            r#"<span class="geo-dec">31.95224°S 115.8614°E</span>
+            <span class="geo">-31.95224; 115.8614</span>"#,
+        );
+        assert_eq!(extract_coordinates(&doc), Some((-31.95224, 115.8614)));
+
+        let doc = Html::parse_document(r#"<span class="geo-dec">59.91°N 10.75°E</span>"#);
+        assert_eq!(extract_coordinates(&doc), Some((59.91, 10.75)));
+
+        let doc = Html::parse_document(
+            r#"<head><meta name="geo.position" content="40.71;-74.01"/></head>"#,
+        );
+        assert_eq!(extract_coordinates(&doc), Some((40.71, -74.01)));
+
+        let doc = Html::parse_document(r#"<span class="geo">95; 10</span>"#);
+        assert_eq!(extract_coordinates(&doc), None);
+    }
+
+    #[test]
+    fn finds_section_headings() {
+        let doc = Html::parse_document(
+            r#"<section data-mw-section-id="0"><table id="lead"></table></section>
+            <section data-mw-section-id="1">
+                <div class="mw-heading mw-heading2"><h2 id="Geography">Geography</h2></div>
+                <table id="geography"></table>
+                <section data-mw-section-id="2">
+                    <h3 id="Climate">Climate</h3>
+                    <table id="climate"></table>
+                </section>
+            </section>"#,
+        );
+        let section = |id: &str| {
+            let table = Selector::parse(&format!("table#{}", id)).unwrap();
+            section_heading(doc.select(&table).next().unwrap())
+        };
+        assert_eq!(section("lead"), None);
+        assert_eq!(section("geography"), Some("Geography".to_string()));
+        assert_eq!(section("climate"), Some("Climate".to_string()));
+    }
+
+    #[test]
+    fn parses_cell_values() {
+        let english = Labels::english();
+        let german = Labels::load(DEFAULT_LABELS, "de").unwrap();
+        let parse = |value| parse_cell_value(value, &english);
+        assert_eq!(parse("12.3[a]"), Ok(CellValue::Number(12.3)));
+        assert_eq!(parse("(−4.5)"), Ok(CellValue::Number(-4.5)));
+        assert_eq!(parse("1,234"), Ok(CellValue::Number(1234.0)));
+        assert_eq!(parse("2,345.6"), Ok(CellValue::Number(2345.6)));
+        assert_eq!(parse("—"), Ok(CellValue::Missing));
+        assert_eq!(parse("n/a"), Ok(CellValue::Missing));
+        assert_eq!(parse("trace"), Ok(CellValue::Trace));
+        assert_eq!(parse("Tr"), Ok(CellValue::Trace));
+        assert_eq!(parse("<0.1"), Ok(CellValue::LessThan(0.1)));
+        assert!(parse("t").is_err());
+        assert!(parse("abc").is_err());
+
+        let parse = |value| parse_cell_value(value, &german);
+        assert_eq!(parse("12,3"), Ok(CellValue::Number(12.3)));
+        assert_eq!(parse("1,234"), Ok(CellValue::Number(1.234)));
+        assert_eq!(parse("1.234,5"), Ok(CellValue::Number(1234.5)));
+        assert_eq!(parse("<0,1"), Ok(CellValue::LessThan(0.1)));
+    }
+
+    #[test]
+    fn normalizes_sunshine_rows() {
+        let months = vec![
+            "Month", "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov",
+            "Dec", "Year",
+        ];
+        let daily = vec![
+            "Mean daily sunshine hours",
+            "1",
+            "2",
+            "3",
+            "4",
+            "5",
+            "6",
+            "7",
+            "8",
+            "9",
+            "10",
+            "11",
+            "12",
+            "6.5",
+        ];
+        let percent = vec![
+            "Percent possible sunshine",
+            "50",
+            "50",
+            "50",
+            "50",
+            "50",
+            "50",
+            "50",
+            "50",
+            "50",
+            "50",
+            "50",
+            "50",
+            "50",
+        ];
+
+        let labels = Labels::english();
+        let sunshine = extract_sunshine_data(&vec![months.clone(), daily], 1, 12, None, &labels);
+        assert_eq!(
+            sunshine.sunshine_conversion,
+            Some(SunshineConversion::DailyTimesDaysInMonth)
+        );
+        assert_eq!(sunshine.sunshine_hours.unwrap()[1], Some(56.49));
+
+        let sunshine =
+            extract_sunshine_data(&vec![months.clone(), percent.clone()], 1, 12, None, &labels);
+        assert_eq!(sunshine.sunshine_hours, None);
+        assert_eq!(sunshine.percent_possible_sunshine.unwrap()[0], Some(50.0));
+
+        let sunshine = extract_sunshine_data(&vec![months, percent], 1, 12, Some(0.0), &labels);
+        assert_eq!(
+            sunshine.sunshine_conversion,
+            Some(SunshineConversion::PercentPossibleTimesDaylight)
+        );
+        assert!(sunshine.daylight_hours.is_some());
+    }
+
+    #[test]
+    fn parses_tables_in_other_languages() {
+        let page = |html: &str| {
+            let page_result = PageResult {
+                page_name: "Klima".to_string(),
+                ..Default::default()
+            };
+            (page_result, Some(html.to_string()))
+        };
+
+        // German tables have no months header and sunshine hours per day.
+        let html = r#"<section><h2 id="Klima">Klima</h2><table class="wikitable">
+            <tr><th></th><th>Jan</th><th>Feb</th><th>Mär</th><th>Apr</th><th>Mai</th><th>Jun</th>
+                <th>Jul</th><th>Aug</th><th>Sep</th><th>Okt</th><th>Nov</th><th>Dez</th></tr>
+            <tr><td>Mittl. Tagesmax. (°C)</td><td>2,9</td><td>4,2</td><td>8,5</td><td>13,2</td>
+                <td>18,9</td><td>21,6</td><td>23,7</td><td>23,6</td><td>18,8</td><td>13,4</td>
+                <td>7,1</td><td>4,4</td></tr>
+            <tr><td>Mittl. Tagesmin. (°C)</td><td>−1,9</td><td>−1,5</td><td>1,3</td><td>4,2</td>
+                <td>9,0</td><td>12,3</td><td>14,3</td><td>14,1</td><td>10,6</td><td>6,4</td>
+                <td>2,2</td><td>−0,4</td></tr>
+            <tr><td>Sonnenstunden (h/d)</td><td>1,5</td><td>2,6</td><td>4,0</td><td>5,8</td>
+                <td>7,5</td><td>7,7</td><td>7,4</td><td>7,2</td><td>5,4</td><td>3,5</td>
+                <td>1,8</td><td>1,3</td></tr>
+        </table></section>"#;
+        let labels = Labels::load(DEFAULT_LABELS, "de").unwrap();
+        let parsed = parse_page(page(html), &labels);
+        assert_eq!(parsed.parse_result, Some(ParseResult::Parsed));
+        assert_eq!(parsed.section, Some("Klima".to_string()));
+        assert_eq!(parsed.average_high_c.unwrap()[0], json!(2.9));
+        assert_eq!(parsed.average_high_f.unwrap()[0], json!(37.2));
+        assert_eq!(parsed.average_low_c.unwrap()[11], json!(-0.4));
+        assert_eq!(
+            parsed.sunshine_conversion,
+            Some(SunshineConversion::DailyTimesDaysInMonth)
+        );
+        assert_eq!(parsed.sunshine_hours.unwrap()[0], json!(46.5));
+
+        // French tables have a months header and sunshine hours per month.
+        let html = r#"<table class="wikitable">
+            <tr><th>Mois</th><th>janv.</th><th>févr.</th><th>mars</th><th>avril</th><th>mai</th>
+                <th>juin</th><th>juil.</th><th>août</th><th>sept.</th><th>oct.</th><th>nov.</th>
+                <th>déc.</th><th>année</th></tr>
+            <tr><td>Température minimale moyenne (°C)</td><td>2,7</td><td>2,8</td><td>5,3</td>
+                <td>7,3</td><td>10,9</td><td>13,8</td><td>15,8</td><td>15,7</td><td>12,7</td>
+                <td>9,6</td><td>5,8</td><td>3,4</td><td>8,8</td></tr>
+            <tr><td>Température maximale moyenne (°C)</td><td>7,2</td><td>8,3</td><td>12,2</td>
+                <td>15,6</td><td>19,6</td><td>22,7</td><td>25,2</td><td>25,0</td><td>20,8</td>
+                <td>16,0</td><td>10,8</td><td>7,5</td><td>15,9</td></tr>
+            <tr><td>Ensoleillement (h)</td><td>62,5</td><td>79,2</td><td>128,9</td><td>166</td>
+                <td>193,8</td><td>202,1</td><td>212,2</td><td>212,1</td><td>167,9</td>
+                <td>117,8</td><td>67,7</td><td>51,4</td><td>1661,6</td></tr>
+        </table>"#;
+        let labels = Labels::load(DEFAULT_LABELS, "fr").unwrap();
+        let parsed = parse_page(page(html), &labels);
+        assert_eq!(parsed.parse_result, Some(ParseResult::Parsed));
+        assert_eq!(parsed.section, None);
+        assert_eq!(parsed.average_high_c.unwrap()[0], json!(7.2));
+        assert_eq!(parsed.average_low_f.unwrap()[0], json!(36.9));
+        assert_eq!(
+            parsed.sunshine_conversion,
+            Some(SunshineConversion::Monthly)
+        );
+        assert_eq!(parsed.sunshine_hours.unwrap()[11], json!(51.4));
+
+        let english = parse_page(page(html), &Labels::english());
+        assert_eq!(english.parse_result, Some(ParseResult::NoValidTablesFound));
+    }
+
+    #[test]
+    fn daylight_hours_follow_the_seasons() {
+        let london = daylight_hours(51.5);
+        assert!(london[5] > 16.0 && london[11] < 8.5);
+        let equator = daylight_hours(0.0);
+        assert!(equator.iter().all(|hours| (hours - 12.1).abs() < 0.1));
+    }
+
     #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+    fn missing_months_are_null() {
+        let (non_paren, paren) =
+            parse_table_temperatures(vec!["10.1", "(50.2)", "—", "(—)"], &Labels::english())
+                .unwrap();
+        assert_eq!(non_paren, json!([10.1, null]));
+        assert_eq!(paren, json!([50.2, null]));
     }
 }
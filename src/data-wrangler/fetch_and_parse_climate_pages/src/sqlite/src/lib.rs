@@ -1,12 +1,45 @@
+extern crate city_climes_core;
+#[macro_use]
 extern crate rusqlite;
-extern crate types;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
 
+mod registry;
+
+pub use registry::{assign_ids, LocationChanges, RegisteredLocation};
+
+use city_climes_core::{Location, PageResult};
 use rusqlite::Connection;
-use types::PageResult;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+/// Creates the results table if the database doesn't have it yet.
+pub fn create_table(db_path: &Path) {
+    let conn = Connection::open(db_path).expect("Failed to open connection to DB.");
+    conn.execute_batch(include_str!(
+        "../../../../../sql/create_fetch_and_parse_results_table.sql"
+    ))
+    .expect("Failed to create FetchAndParseResults table.");
+}
 
-pub fn save_page(db_path: &str, page_result: PageResult) -> () {
+/// Saves a page's result, replacing the title's earlier failed fetch, if any,
+/// instead of adding another row beside it.
+pub fn save_page(db_path: &Path, page_result: PageResult) {
     let conn = Connection::open(db_path).expect("Failed to open connection to DB.");
 
+    let res = conn.execute(
+        "DELETE FROM FetchAndParseResults
+            WHERE PageName = ?1 AND FetchResult IN ('FetchError', 'StatusError')",
+        [&page_result.page_name],
+    );
+    if let Err(err) = res {
+        println!("Save -> Error: {:?}", err);
+    }
+
     let res = conn.execute(
         "INSERT INTO FetchAndParseResults (
                 PageName,
@@ -14,34 +47,48 @@ pub fn save_page(db_path: &str, page_result: PageResult) -> () {
                 ResponseURL,
                 StatusCode,
                 ContentLocationURL,
+                PageID,
                 WikipediaURL,
                 LocationName,
+                Latitude,
+                Longitude,
                 TableHTML,
+                Section,
                 TemperatureTableType,
                 AverageHighC,
                 AverageLowC,
                 AverageHighF,
                 AverageLowF,
                 SunshineHours,
+                PercentPossibleSunshine,
+                DaylightHours,
+                SunshineConversion,
                 ParseResult
             ) VALUES (
-                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8,
-                ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
-        &[
+                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10,
+                ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
+        params![
             &page_result.page_name,
             &page_result.fetch_result,
             &page_result.response_url,
             &page_result.status_code,
             &page_result.content_location_url,
+            &page_result.page_id,
             &page_result.wikipedia_url,
             &page_result.location_name,
+            &page_result.latitude,
+            &page_result.longitude,
             &page_result.table_html,
+            &page_result.section,
             &page_result.temperature_table_type,
             &page_result.average_high_c,
             &page_result.average_low_c,
             &page_result.average_high_f,
             &page_result.average_low_f,
             &page_result.sunshine_hours,
+            &page_result.percent_possible_sunshine,
+            &page_result.daylight_hours,
+            &page_result.sunshine_conversion,
             &page_result.parse_result,
         ],
     );
@@ -56,10 +103,455 @@ pub fn save_page(db_path: &str, page_result: PageResult) -> () {
     }
 }
 
+/// Canonical keys (see `PageResult::canonical_keys`) of every page already
+/// saved, so pages fetched in an earlier run are not parsed and saved again.
+pub fn fetched_keys(db_path: &Path) -> HashSet<String> {
+    let conn = Connection::open(db_path).expect("Failed to open connection to DB.");
+    let mut stmt = conn
+        .prepare(
+            "SELECT PageID, ContentLocationURL
+            FROM FetchAndParseResults
+            WHERE FetchResult = 'Page'",
+        )
+        .expect("Failed to prepare fetched pages query.");
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(PageResult {
+                page_id: row.get(0)?,
+                content_location_url: row.get(1)?,
+                ..Default::default()
+            }
+            .canonical_keys())
+        })
+        .expect("Failed to query fetched pages.");
+
+    rows.filter_map(|keys| keys.ok()).flatten().collect()
+}
+
+/// Titles of every page already saved, under their own name or as an alias
+/// of the page they redirect to, so they aren't fetched again.
+pub fn saved_titles(db_path: &Path) -> HashSet<String> {
+    let conn = Connection::open(db_path).expect("Failed to open connection to DB.");
+    let mut stmt = conn
+        .prepare(
+            "SELECT PageName, AliasTitles
+            FROM FetchAndParseResults
+            WHERE FetchResult = 'Page'",
+        )
+        .expect("Failed to prepare saved titles query.");
+
+    let rows = stmt
+        .query_map([], |row| {
+            let page_name: String = row.get(0)?;
+            let alias_titles: Option<Value> = row.get(1)?;
+            let alias_titles: Vec<String> = alias_titles
+                .and_then(|value| serde_json::from_value(value).ok())
+                .unwrap_or_default();
+            Ok(Some(page_name).into_iter().chain(alias_titles))
+        })
+        .expect("Failed to query saved titles.");
+
+    rows.filter_map(|titles| titles.ok()).flatten().collect()
+}
+
+/// Which parsed locations `export` writes.
+#[derive(Debug, Default, Clone)]
+pub struct ExportOptions {
+    /// Leave out locations without sunshine hours.
+    pub require_sunshine: bool,
+    /// Leave out locations south of this latitude, or without coordinates.
+    pub min_latitude: Option<f64>,
+    /// Leave out locations north of this latitude, or without coordinates.
+    pub max_latitude: Option<f64>,
+}
+
+impl ExportOptions {
+    fn includes(&self, location: &Location) -> bool {
+        let sunshine = !self.require_sunshine || location.SunshineHours.is_some();
+        let in_bounds = match (self.min_latitude, self.max_latitude, location.Latitude) {
+            (None, None, _) => true,
+            (_, _, None) => false,
+            (min, max, Some(latitude)) => {
+                min.is_none_or(|min| latitude >= min) && max.is_none_or(|max| latitude <= max)
+            }
+        };
+        sunshine && in_bounds
+    }
+}
+
+/// The parsed locations, one per location name, as the website's JSON.
+///
+/// A location's ID is its Wikipedia page id, which stays the same when the
+/// page is fetched again or renamed, so IDs don't change between exports.
+/// Rows saved without a page id are left out.
+pub fn export(db_path: &Path, options: &ExportOptions) -> rusqlite::Result<Vec<Location>> {
+    let conn = Connection::open(db_path)?;
+    let mut stmt = conn.prepare(include_str!(
+        "../../../../../sql/select_all_data_by_distinct_locationame_for_json.sql"
+    ))?;
+
+    let rows = stmt.query_map([], |row| {
+        let page_id: Option<u32> = row.get(1)?;
+        let location = Location {
+            ID: page_id.unwrap_or_default(),
+            WikipediaURL: row.get(2)?,
+            LocationName: row.get(3)?,
+            AverageHighC: month_values(row.get(4)?),
+            AverageLowC: month_values(row.get(5)?),
+            AverageHighF: month_values(row.get(6)?),
+            AverageLowF: month_values(row.get(7)?),
+            SunshineHours: row.get::<_, Option<Value>>(8)?.map(month_values),
+            Country: None,
+            CountrySource: None,
+            ISOCode: None,
+            ISOAlpha3: None,
+            ISONumeric: None,
+            Admin1: None,
+            Admin1ISOCode: None,
+            Admin2: None,
+            Continent: None,
+            GeonameID: None,
+            Latitude: row.get(9)?,
+            Longitude: row.get(10)?,
+            Population: None,
+            Elevation: None,
+            Timezone: None,
+        };
+        Ok((page_id, location))
+    })?;
+
+    let mut location_names = HashSet::new();
+    let mut locations = Vec::new();
+    for row in rows {
+        let (page_id, location) = row?;
+        // Rows without a page id are skipped before a location's best row is
+        // picked, so the location comes from its next best row.
+        if page_id.is_none() {
+            println!("Export -> No Page ID: {:?}", &location.LocationName);
+            continue;
+        }
+        // Rows are ordered best first within each location name.
+        if !location_names.insert(location.LocationName.clone()) {
+            continue;
+        }
+        if options.includes(&location) {
+            locations.push(location);
+        }
+    }
+
+    Ok(locations)
+}
+
+fn month_values(value: Value) -> Vec<Option<f64>> {
+    serde_json::from_value(value).unwrap_or_default()
+}
+
+struct FetchedRow {
+    id: i64,
+    page_name: String,
+    alias_titles: Vec<String>,
+    parsed: bool,
+}
+
+/// Merges rows that resolved to the same Wikipedia page into one, keeping the
+/// best parsed row and recording the page names of the others as its aliases.
+pub fn merge_duplicates(db_path: &Path) {
+    let conn = Connection::open(db_path).expect("Failed to open connection to DB.");
+
+    let mut pages: BTreeMap<String, Vec<FetchedRow>> = BTreeMap::new();
+    {
+        let mut stmt = conn
+            .prepare(
+                "SELECT ID, PageName, PageID, ContentLocationURL, AliasTitles, ParseResult
+                FROM FetchAndParseResults
+                WHERE FetchResult IN ('Page', 'Duplicate')
+                ORDER BY ID",
+            )
+            .expect("Failed to prepare duplicates query.");
+
+        let rows = stmt
+            .query_map([], |row| {
+                let page_id: Option<u32> = row.get(2)?;
+                let url: Option<String> = row.get(3)?;
+                let alias_titles: Option<Value> = row.get(4)?;
+                let parse_result: Option<String> = row.get(5)?;
+                let key = match (page_id, url) {
+                    (Some(page_id), _) => format!("page_id:{}", page_id),
+                    (None, Some(url)) => format!("url:{}", url),
+                    (None, None) => String::new(),
+                };
+                let alias_titles = alias_titles
+                    .and_then(|value| serde_json::from_value(value).ok())
+                    .unwrap_or_default();
+                Ok((
+                    key,
+                    FetchedRow {
+                        id: row.get(0)?,
+                        page_name: row.get(1)?,
+                        alias_titles,
+                        parsed: parse_result == Some("Parsed".to_string()),
+                    },
+                ))
+            })
+            .expect("Failed to query duplicates.");
+
+        for row in rows.filter_map(|row| row.ok()) {
+            let (key, fetched_row) = row;
+            if !key.is_empty() {
+                pages.entry(key).or_default().push(fetched_row);
+            }
+        }
+    }
+
+    for (key, mut rows) in pages.into_iter().filter(|(_, rows)| rows.len() > 1) {
+        // Parsed rows first, then the earliest fetched.
+        rows.sort_by_key(|row| (!row.parsed, row.id));
+        let keep = rows.remove(0);
+
+        let mut alias_titles = keep.alias_titles.clone();
+        for row in &rows {
+            for title in Some(&row.page_name)
+                .into_iter()
+                .chain(row.alias_titles.iter())
+            {
+                if *title != keep.page_name && !alias_titles.contains(title) {
+                    alias_titles.push(title.to_string());
+                }
+            }
+        }
+
+        let res = conn.execute(
+            "UPDATE FetchAndParseResults SET AliasTitles = ?1 WHERE ID = ?2",
+            params![json!(alias_titles), keep.id],
+        );
+        if res.is_err() {
+            println!("Merge -> Error: {:?}", res);
+            continue;
+        }
+
+        for row in rows {
+            let res = conn.execute("DELETE FROM FetchAndParseResults WHERE ID = ?1", [row.id]);
+            if res.is_err() {
+                println!("Merge -> Error: {:?}", res);
+            }
+        }
+
+        println!(
+            "Merge -> Merged: {:?} with aliases {:?} ({})",
+            &keep.page_name, alias_titles, key
+        );
+    }
+}
+
+/// Deletes the rows of pages that are no longer listed, e.g. of cities
+/// dropped from the geonames file, so a database kept between runs only
+/// exports listed pages. A merged row stays while its page name or one of its
+/// alias titles is listed.
+pub fn remove_unlisted_pages(db_path: &Path, pages: &HashSet<String>) {
+    let conn = Connection::open(db_path).expect("Failed to open connection to DB.");
+
+    let unlisted: Vec<(i64, String)> = {
+        let mut stmt = conn
+            .prepare("SELECT ID, PageName, AliasTitles FROM FetchAndParseResults")
+            .expect("Failed to prepare unlisted pages query.");
+
+        let rows = stmt
+            .query_map([], |row| {
+                let page_name: String = row.get(1)?;
+                let alias_titles: Option<Value> = row.get(2)?;
+                let alias_titles: Vec<String> = alias_titles
+                    .and_then(|value| serde_json::from_value(value).ok())
+                    .unwrap_or_default();
+                let listed = pages.contains(&page_name)
+                    || alias_titles.iter().any(|title| pages.contains(title));
+                Ok((row.get(0)?, page_name, listed))
+            })
+            .expect("Failed to query unlisted pages.");
+
+        rows.filter_map(|row| row.ok())
+            .filter(|(_, _, listed)| !listed)
+            .map(|(id, page_name, _)| (id, page_name))
+            .collect()
+    };
+
+    for (id, page_name) in unlisted {
+        match conn.execute("DELETE FROM FetchAndParseResults WHERE ID = ?1", [id]) {
+            Ok(_) => println!("Remove -> Unlisted: {:?}", page_name),
+            Err(err) => println!("Remove -> Error: {:?}", err),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use city_climes_core::{FetchResult, ParseResult};
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn test_db(name: &str) -> PathBuf {
+        let db_path = env::temp_dir().join(format!("city_climes_{}.db", name));
+        let _ = fs::remove_file(&db_path);
+        create_table(&db_path);
+        db_path
+    }
+
+    fn page(page_name: &str, fetch_result: FetchResult, parsed: bool) -> PageResult {
+        PageResult {
+            page_name: page_name.to_string(),
+            fetch_result,
+            page_id: Some(645042),
+            content_location_url: Some(
+                "https://en.wikipedia.org/api/rest_v1/page/html/Climate_of_New_York_City"
+                    .to_string(),
+            ),
+            parse_result: if parsed {
+                Some(ParseResult::Parsed)
+            } else {
+                None
+            },
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn merges_duplicate_pages() {
+        let db_path = test_db("merges_duplicate_pages");
+        save_page(
+            &db_path,
+            page("Climate of NYC", FetchResult::Duplicate, false),
+        );
+        save_page(
+            &db_path,
+            page("Climate of New York City", FetchResult::Page, true),
+        );
+
+        assert!(fetched_keys(&db_path).contains("page_id:645042"));
+
+        merge_duplicates(&db_path);
+        let titles = saved_titles(&db_path);
+        assert!(titles.contains("Climate of New York City"));
+        assert!(titles.contains("Climate of NYC"));
+
+        let conn = Connection::open(&db_path).unwrap();
+        let (page_name, alias_titles): (String, Value) = conn
+            .query_row(
+                "SELECT PageName, AliasTitles FROM FetchAndParseResults",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(page_name, "Climate of New York City");
+        assert_eq!(alias_titles, json!(["Climate of NYC"]));
+    }
+
+    #[test]
+    fn replaces_failed_fetches() {
+        let db_path = test_db("replaces_failed_fetches");
+        save_page(&db_path, page("Perth", FetchResult::StatusError, false));
+        save_page(&db_path, page("Perth", FetchResult::FetchError, false));
+        assert!(saved_titles(&db_path).is_empty());
+        save_page(&db_path, page("Perth", FetchResult::Page, true));
+
+        let conn = Connection::open(&db_path).unwrap();
+        let fetch_results: String = conn
+            .query_row(
+                "SELECT group_concat(FetchResult) FROM FetchAndParseResults",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(fetch_results, "Page");
+        assert!(saved_titles(&db_path).contains("Perth"));
+    }
+
+    #[test]
+    fn exports_parsed_locations() {
+        let db_path = test_db("exports_parsed_locations");
+        save_page(
+            &db_path,
+            PageResult {
+                location_name: Some("New York City".to_string()),
+                wikipedia_url: Some(
+                    "https://en.wikipedia.org/wiki/Climate_of_New_York_City".to_string(),
+                ),
+                average_high_c: Some(json!([3.9, null])),
+                average_low_c: Some(json!([-2.8, -1.7])),
+                average_high_f: Some(json!([39.0, null])),
+                average_low_f: Some(json!([27.0, 29.0])),
+                ..page("Climate of New York City", FetchResult::Page, true)
+            },
+        );
+        save_page(
+            &db_path,
+            page("Climate of NYC", FetchResult::Duplicate, false),
+        );
+
+        // A better row of the same location, but without a page id.
+        save_page(
+            &db_path,
+            PageResult {
+                page_id: None,
+                location_name: Some("New York City".to_string()),
+                wikipedia_url: Some("https://en.wikipedia.org/wiki/New_York_City".to_string()),
+                sunshine_hours: Some(json!([162.7, 163.1])),
+                ..page("New York City", FetchResult::Page, true)
+            },
+        );
+
+        let locations = export(&db_path, &ExportOptions::default()).unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].ID, 645042);
+        assert_eq!(locations[0].LocationName, "New York City");
+        assert_eq!(locations[0].AverageHighC, vec![Some(3.9), None]);
+        assert_eq!(locations[0].SunshineHours, None);
+
+        let options = ExportOptions {
+            require_sunshine: true,
+            ..Default::default()
+        };
+        assert!(export(&db_path, &options).unwrap().is_empty());
+
+        let options = ExportOptions {
+            min_latitude: Some(0.0),
+            ..Default::default()
+        };
+        assert!(export(&db_path, &options).unwrap().is_empty());
+    }
+
+    #[test]
+    fn removes_unlisted_pages() {
+        let db_path = test_db("removes_unlisted_pages");
+        save_page(
+            &db_path,
+            page("Climate of New York City", FetchResult::Page, true),
+        );
+        save_page(
+            &db_path,
+            page("Climate of NYC", FetchResult::Duplicate, false),
+        );
+        save_page(&db_path, page("Perth", FetchResult::StatusError, false));
+        merge_duplicates(&db_path);
+
+        let pages = vec!["Climate of NYC".to_string()].into_iter().collect();
+        remove_unlisted_pages(&db_path, &pages);
+
+        let conn = Connection::open(&db_path).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT PageName FROM FetchAndParseResults")
+            .unwrap();
+        let page_names: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(page_names, vec!["Climate of New York City"]);
+    }
 }
@@ -0,0 +1,216 @@
+use city_climes_core::Location;
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// A location in the ID registry.
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RegisteredLocation {
+    pub ID: u32,
+    pub PageID: Option<u32>,
+    pub LocationName: String,
+}
+
+/// Locations that appeared in or dropped out of the export since the last
+/// time IDs were assigned.
+#[derive(Debug, Default, Serialize)]
+pub struct LocationChanges {
+    pub added: Vec<RegisteredLocation>,
+    pub removed: Vec<RegisteredLocation>,
+}
+
+struct Entry {
+    location: RegisteredLocation,
+    exported: bool,
+    claimed: bool,
+}
+
+/// Replaces the IDs of exported locations, which are their Wikipedia page
+/// ids, with the IDs kept for them in a registry database, so a location
+/// keeps its ID when its row comes from a different page, e.g. "Perth"
+/// instead of "Climate of Perth", or when its page is renamed.
+///
+/// A location is matched by page id first, then by name. A new location is
+/// registered with its page id as its ID, unless that ID is taken.
+pub fn assign_ids(
+    registry_path: &Path,
+    locations: &mut [Location],
+) -> rusqlite::Result<LocationChanges> {
+    let mut conn = Connection::open(registry_path)?;
+    conn.execute_batch(include_str!(
+        "../../../../../sql/create_location_ids_table.sql"
+    ))?;
+
+    let mut entries: Vec<Entry> = {
+        let mut stmt =
+            conn.prepare("SELECT ID, PageID, LocationName, Exported FROM LocationIDs ORDER BY ID")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Entry {
+                location: RegisteredLocation {
+                    ID: row.get(0)?,
+                    PageID: row.get(1)?,
+                    LocationName: row.get(2)?,
+                },
+                exported: row.get(3)?,
+                claimed: false,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    let mut used_ids: HashSet<u32> = entries.iter().map(|entry| entry.location.ID).collect();
+    let mut changes = LocationChanges::default();
+    let tx = conn.transaction()?;
+
+    for location in locations.iter_mut() {
+        let page_id = location.ID;
+        let index = entries
+            .iter()
+            .position(|entry| !entry.claimed && entry.location.PageID == Some(page_id))
+            .or_else(|| {
+                entries.iter().position(|entry| {
+                    !entry.claimed && entry.location.LocationName == location.LocationName
+                })
+            });
+
+        match index {
+            Some(index) => {
+                let entry = &mut entries[index];
+                entry.claimed = true;
+                location.ID = entry.location.ID;
+
+                let registered = RegisteredLocation {
+                    ID: entry.location.ID,
+                    PageID: Some(page_id),
+                    LocationName: location.LocationName.clone(),
+                };
+                if registered != entry.location || !entry.exported {
+                    tx.execute(
+                        "UPDATE LocationIDs SET PageID = ?1, LocationName = ?2, Exported = 1
+                        WHERE ID = ?3",
+                        params![registered.PageID, &registered.LocationName, registered.ID],
+                    )?;
+                }
+                if !entry.exported {
+                    changes.added.push(registered.clone());
+                }
+                entry.location = registered;
+            }
+            None => {
+                let id = if used_ids.contains(&page_id) {
+                    used_ids.iter().max().map_or(1, |max| max + 1)
+                } else {
+                    page_id
+                };
+                used_ids.insert(id);
+                location.ID = id;
+
+                let registered = RegisteredLocation {
+                    ID: id,
+                    PageID: Some(page_id),
+                    LocationName: location.LocationName.clone(),
+                };
+                tx.execute(
+                    "INSERT INTO LocationIDs (ID, PageID, LocationName) VALUES (?1, ?2, ?3)",
+                    params![registered.ID, registered.PageID, &registered.LocationName],
+                )?;
+                changes.added.push(registered.clone());
+                entries.push(Entry {
+                    location: registered,
+                    exported: true,
+                    claimed: true,
+                });
+            }
+        }
+    }
+
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.exported && !entry.claimed)
+    {
+        tx.execute(
+            "UPDATE LocationIDs SET Exported = 0 WHERE ID = ?1",
+            [entry.location.ID],
+        )?;
+        changes.removed.push(entry.location.clone());
+    }
+
+    tx.commit()?;
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn location(page_id: u32, location_name: &str) -> Location {
+        Location {
+            ID: page_id,
+            WikipediaURL: String::new(),
+            LocationName: location_name.to_string(),
+            AverageHighC: vec![],
+            AverageLowC: vec![],
+            AverageHighF: vec![],
+            AverageLowF: vec![],
+            SunshineHours: None,
+            Country: None,
+            CountrySource: None,
+            ISOCode: None,
+            ISOAlpha3: None,
+            ISONumeric: None,
+            Admin1: None,
+            Admin1ISOCode: None,
+            Admin2: None,
+            Continent: None,
+            GeonameID: None,
+            Latitude: None,
+            Longitude: None,
+            Population: None,
+            Elevation: None,
+            Timezone: None,
+        }
+    }
+
+    fn ids(locations: &[Location]) -> Vec<u32> {
+        locations.iter().map(|location| location.ID).collect()
+    }
+
+    #[test]
+    fn keeps_ids_across_exports() {
+        let registry_path = env::temp_dir().join("city_climes_keeps_ids_across_exports.db");
+        let _ = fs::remove_file(&registry_path);
+
+        let mut first = vec![location(10, "Oslo"), location(20, "Perth")];
+        let changes = assign_ids(&registry_path, &mut first).unwrap();
+        assert_eq!(ids(&first), vec![10, 20]);
+        assert_eq!(changes.added.len(), 2);
+
+        // Oslo's page was renamed and Perth now comes from another page.
+        let mut second = vec![
+            location(10, "Christiania"),
+            location(40, "Lima"),
+            location(30, "Perth"),
+        ];
+        let changes = assign_ids(&registry_path, &mut second).unwrap();
+        assert_eq!(ids(&second), vec![10, 40, 20]);
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(changes.added[0].LocationName, "Lima");
+        assert!(changes.removed.is_empty());
+
+        // Perth's old page id is now another page, but its ID is still Perth's.
+        let mut third = vec![location(30, "Perth"), location(20, "Quito")];
+        let changes = assign_ids(&registry_path, &mut third).unwrap();
+        assert_eq!(ids(&third), vec![20, 41]);
+        assert_eq!(changes.added.len(), 1);
+        let removed: Vec<&str> = changes
+            .removed
+            .iter()
+            .map(|location| location.LocationName.as_str())
+            .collect();
+        assert_eq!(removed, vec!["Christiania", "Lima"]);
+    }
+}
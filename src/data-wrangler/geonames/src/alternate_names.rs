@@ -0,0 +1,184 @@
+//! Rows of `alternateNamesV2.txt`, the names of places in each language.
+
+use records::{parse_field, read_tsv, Malformed, Records};
+use std::io;
+use std::io::BufRead;
+
+/// The number of columns in `alternateNamesV2.txt`.
+pub const ALTERNATE_NAME_COLUMNS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlternateName<'a> {
+    pub alternate_name_id: u32,
+    pub geonameid: u32,
+    /// An ISO 639 language code, e.g. "en", or a pseudo language, e.g. "link"
+    /// for a URL or "post" for a postal code. Blank when unknown.
+    pub isolanguage: &'a str,
+    pub name: &'a str,
+    pub is_preferred_name: bool,
+    pub is_short_name: bool,
+    pub is_colloquial: bool,
+    /// A former name, e.g. "Christiania" for Oslo.
+    pub is_historic: bool,
+    /// When the name was used, e.g. "1624".
+    pub from: &'a str,
+    pub to: &'a str,
+}
+
+impl<'a> AlternateName<'a> {
+    /// The title of the Wikipedia page a `link` row points to in a language,
+    /// e.g. "Perth, Scotland" for "https://en.wikipedia.org/wiki/Perth,_Scotland".
+    pub fn wikipedia_title(&self, language: &str) -> Option<String> {
+        if self.isolanguage != "link" {
+            return None;
+        }
+        let prefix = format!("{}.wikipedia.org/wiki/", language);
+        let name = self
+            .name
+            .trim_start_matches("https://")
+            .trim_start_matches("http://");
+        let title = name.strip_prefix(&prefix)?;
+        Some(percent_decode(title).replace('_', " ")).filter(|title| !title.is_empty())
+    }
+
+    /// A name the place goes by in a language now: one in the language or
+    /// in no particular language, and not a former one.
+    pub fn is_current_name_in(&self, language: &str) -> bool {
+        !self.is_historic && (self.isolanguage == language || self.isolanguage.is_empty())
+    }
+}
+
+/// The alternate names in an alternate names file.
+pub fn parse_alternate_names(contents: &str) -> Records<Vec<AlternateName<'_>>> {
+    read_tsv(contents, ALTERNATE_NAME_COLUMNS, parse_alternate_name)
+}
+
+/// Reads an alternate names file a line at a time, the way `stream_cities`
+/// does; the whole file is several gigabytes.
+pub fn stream_alternate_names<R, F>(mut reader: R, mut f: F) -> io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(Result<AlternateName, Malformed>),
+{
+    let mut line = String::new();
+    let mut number = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        number += 1;
+
+        let mut records: Records<Vec<AlternateName>> = read_tsv(
+            line.trim_end_matches(&['\r', '\n'][..]),
+            ALTERNATE_NAME_COLUMNS,
+            parse_alternate_name,
+        );
+        if let Some(mut malformed) = records.malformed.pop() {
+            malformed.line = number;
+            f(Err(malformed));
+        } else if let Some(alternate_name) = records.rows.pop() {
+            f(Ok(alternate_name));
+        }
+    }
+}
+
+fn parse_alternate_name<'a>(cols: &[&'a str]) -> Result<AlternateName<'a>, String> {
+    Ok(AlternateName {
+        alternate_name_id: parse_field("alternateNameId", cols[0])?,
+        geonameid: parse_field("geonameid", cols[1])?,
+        isolanguage: cols[2].trim(),
+        name: cols[3].trim(),
+        is_preferred_name: cols[4].trim() == "1",
+        is_short_name: cols[5].trim() == "1",
+        is_colloquial: cols[6].trim() == "1",
+        is_historic: cols[7].trim() == "1",
+        from: cols[8].trim(),
+        to: cols[9].trim(),
+    })
+}
+
+/// Decodes the `%C3%A9` escapes in a URL path. Bad escapes are kept as they
+/// are.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = if bytes[i] == b'%' {
+            value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALTERNATE_NAMES: &str = "\
+1\t3143244\ten\tOslo\t1\t\t\t\t\t
+2\t3143244\t\tChristiania\t\t\t\t1\t1624\t1925
+3\t3143244\tlink\thttps://en.wikipedia.org/wiki/Oslo\t\t\t\t\t\t
+4\t3143244\tde\tOslo\t\t\t\t\t\t
+5\t2063523\tlink\thttps://en.wikipedia.org/wiki/Perth%2C_Western_Australia\t\t\t\t\t\t
+6\t2063523\tlink\thttps://fr.wikipedia.org/wiki/Perth_(Australie)\t\t\t\t\t\t
+";
+
+    #[test]
+    fn reads_language_tags() {
+        let names = parse_alternate_names(ALTERNATE_NAMES).rows;
+        assert_eq!(names.len(), 6);
+        assert!(names[0].is_preferred_name);
+        assert!(names[0].is_current_name_in("en"));
+        assert!(names[1].is_historic);
+        assert_eq!((names[1].from, names[1].to), ("1624", "1925"));
+        assert!(!names[1].is_current_name_in("en"));
+        assert!(!names[3].is_current_name_in("en"));
+    }
+
+    #[test]
+    fn reads_wikipedia_titles() {
+        let names = parse_alternate_names(ALTERNATE_NAMES).rows;
+        let titles: Vec<Option<String>> = names
+            .iter()
+            .map(|name| name.wikipedia_title("en"))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                None,
+                None,
+                Some("Oslo".to_string()),
+                None,
+                Some("Perth, Western Australia".to_string()),
+                None,
+            ]
+        );
+        assert_eq!(percent_decode("S%C3%A3o_Paulo%2"), "S\u{e3}o_Paulo%2");
+    }
+
+    #[test]
+    fn streams_alternate_names() {
+        let mut ids = Vec::new();
+        stream_alternate_names(ALTERNATE_NAMES.as_bytes(), |name| {
+            ids.push(name.unwrap().alternate_name_id)
+        })
+        .unwrap();
+        assert_eq!(ids, vec![1, 2, 3, 4, 5, 6]);
+    }
+}
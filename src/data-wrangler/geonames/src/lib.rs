@@ -0,0 +1,474 @@
+//! Rows of the geonames.org dump files, see
+//! http://download.geonames.org/export/dump/readme.txt
+
+mod alternate_names;
+pub mod records;
+
+pub use alternate_names::{
+    parse_alternate_names, stream_alternate_names, AlternateName, ALTERNATE_NAME_COLUMNS,
+};
+use records::{parse_field, read_tsv, Malformed, Records};
+use std::collections::HashMap;
+use std::io;
+use std::io::BufRead;
+
+/// A row of a cities file, e.g. `cities15000.txt`, or of `allCountries.txt`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeonamesCity<'a> {
+    pub geonameid: u32,
+    pub name: &'a str,
+    pub ascii_name: &'a str,
+    pub alternate_names: Vec<&'a str>,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// E.g. "P" for cities and villages.
+    pub feature_class: &'a str,
+    /// E.g. "PPLC" for a capital, see http://www.geonames.org/export/codes.html
+    pub feature_code: &'a str,
+    pub country_code: &'a str,
+    /// Other countries' ISO codes, for places in more than one.
+    pub cc2: Vec<&'a str>,
+    /// E.g. "08", the code of the state or province in `admin1CodesASCII.txt`.
+    pub admin1_code: &'a str,
+    /// E.g. "037", the code of the county in `admin2Codes.txt`.
+    pub admin2_code: &'a str,
+    pub admin3_code: &'a str,
+    pub admin4_code: &'a str,
+    pub population: u64,
+    /// In metres.
+    pub elevation: Option<i32>,
+    /// The digital elevation model's elevation in metres, see
+    /// http://www.geonames.org/export/web-services.html#srtm3
+    pub dem: Option<i32>,
+    pub timezone: &'a str,
+    /// E.g. "2020-01-01".
+    pub modification_date: &'a str,
+}
+
+impl<'a> GeonamesCity<'a> {
+    /// The name, ASCII name and alternate names, without blanks.
+    pub fn names(&self) -> Vec<&'a str> {
+        let mut names = vec![self.name, self.ascii_name];
+        names.extend(self.alternate_names.iter().cloned());
+        names.retain(|name| !name.is_empty());
+        names
+    }
+
+    /// The elevation, or else the digital elevation model's.
+    pub fn elevation_or_dem(&self) -> Option<i32> {
+        self.elevation.or(self.dem)
+    }
+}
+
+/// The number of columns in a cities file.
+pub const CITY_COLUMNS: usize = 19;
+
+/// The cities in a cities file.
+pub fn parse_cities(contents: &str) -> Records<Vec<GeonamesCity<'_>>> {
+    read_tsv(contents, CITY_COLUMNS, parse_city)
+}
+
+/// Reads a cities file a line at a time, so files too big to hold, e.g.
+/// `allCountries.txt`, can be read. Each city, or the line number of each
+/// malformed row, is passed to `f`.
+pub fn stream_cities<R, F>(mut reader: R, mut f: F) -> io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(Result<GeonamesCity, Malformed>),
+{
+    let mut line = String::new();
+    let mut number = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        number += 1;
+
+        let mut records: Records<Vec<GeonamesCity>> = read_tsv(
+            line.trim_end_matches(&['\r', '\n'][..]),
+            CITY_COLUMNS,
+            parse_city,
+        );
+        if let Some(mut malformed) = records.malformed.pop() {
+            malformed.line = number;
+            f(Err(malformed));
+        } else if let Some(city) = records.rows.pop() {
+            f(Ok(city));
+        }
+    }
+}
+
+fn parse_city<'a>(cols: &[&'a str]) -> Result<GeonamesCity<'a>, String> {
+    Ok(GeonamesCity {
+        geonameid: parse_field("geonameid", cols[0])?,
+        name: cols[1].trim(),
+        ascii_name: cols[2].trim(),
+        alternate_names: list(cols[3]),
+        latitude: parse_field("latitude", cols[4])?,
+        longitude: parse_field("longitude", cols[5])?,
+        feature_class: cols[6].trim(),
+        feature_code: cols[7].trim(),
+        country_code: cols[8].trim(),
+        cc2: list(cols[9]),
+        admin1_code: cols[10].trim(),
+        admin2_code: cols[11].trim(),
+        admin3_code: cols[12].trim(),
+        admin4_code: cols[13].trim(),
+        population: cols[14].trim().parse().unwrap_or_default(),
+        elevation: cols[15].trim().parse().ok(),
+        // -9999 is the DEM's value for no data, e.g. at sea.
+        dem: cols[16].trim().parse().ok().filter(|dem| *dem != -9999),
+        timezone: cols[17].trim(),
+        modification_date: cols[18].trim(),
+    })
+}
+
+/// A comma separated list column, without blanks.
+fn list(column: &str) -> Vec<&str> {
+    column
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Which cities to keep, e.g. capitals of at least 100,000 people.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Keep only cities with one of these feature codes; all of them if
+    /// there are none.
+    pub feature_codes: Vec<String>,
+    pub min_population: u64,
+}
+
+impl Filter {
+    pub fn matches(&self, city: &GeonamesCity) -> bool {
+        city.population >= self.min_population
+            && (self.feature_codes.is_empty()
+                || self
+                    .feature_codes
+                    .iter()
+                    .any(|code| code == city.feature_code))
+    }
+}
+
+/// The cities by geonameid.
+pub fn by_id<'c, 'a>(cities: &'c [GeonamesCity<'a>]) -> HashMap<u32, &'c GeonamesCity<'a>> {
+    cities.iter().map(|city| (city.geonameid, city)).collect()
+}
+
+/// The columns of a `countryInfo.txt` row used here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountryInfo<'a> {
+    pub iso: &'a str,
+    /// ISO 3166-1 alpha-3 code, e.g. "AUS".
+    pub iso3: &'a str,
+    /// ISO 3166-1 numeric code with its leading zeros, e.g. "036".
+    pub iso_numeric: &'a str,
+    pub country: &'a str,
+    pub continent: &'a str,
+}
+
+/// The countries in a `countryInfo.txt` file by ISO code. The file starts
+/// with comment lines.
+pub fn parse_country_info(contents: &str) -> Records<HashMap<&str, CountryInfo<'_>>> {
+    read_tsv(contents, 9, |cols| {
+        let country_info = CountryInfo {
+            iso: cols[0].trim(),
+            iso3: cols[1].trim(),
+            iso_numeric: cols[2].trim(),
+            country: cols[4].trim(),
+            continent: cols[8].trim(),
+        };
+        Ok((country_info.iso, country_info))
+    })
+}
+
+/// A row of `admin1CodesASCII.txt` or `admin2Codes.txt`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Admin<'a> {
+    /// E.g. "AU.08" or "US.CA.037".
+    pub code: &'a str,
+    pub name: &'a str,
+    pub ascii_name: &'a str,
+    pub geonameid: u32,
+}
+
+/// The rows of an admin codes file by code.
+pub fn parse_admin_codes(contents: &str) -> Records<HashMap<&str, Admin<'_>>> {
+    read_tsv(contents, 4, |cols| {
+        let admin = Admin {
+            code: cols[0].trim(),
+            name: cols[1].trim(),
+            ascii_name: cols[2].trim(),
+            geonameid: parse_field("geonameid", cols[3])?,
+        };
+        Ok((admin.code, admin))
+    })
+}
+
+/// ISO 3166-2 subdivision codes by admin1 code, e.g. "AU.08" -> "AU-WA",
+/// from a file of tab separated pairs. Geonames admin1 codes are often not
+/// the ISO ones, so the pairs come from elsewhere, e.g. Wikidata.
+pub fn parse_subdivision_codes(contents: &str) -> Records<HashMap<&str, &str>> {
+    read_tsv(contents, 2, |cols| {
+        let iso = cols[1].trim();
+        if iso.is_empty() {
+            return Err(format!("{} has no ISO 3166-2 code", cols[0].trim()));
+        }
+        Ok((cols[0].trim(), iso))
+    })
+}
+
+/// The states or provinces and counties cities are in.
+#[derive(Debug, Default)]
+pub struct Subdivisions<'a> {
+    pub admin1: HashMap<&'a str, Admin<'a>>,
+    pub admin2: HashMap<&'a str, Admin<'a>>,
+    pub iso_codes: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Subdivisions<'a> {
+    pub fn admin1(&self, country_code: &str, admin1_code: &str) -> Option<&Admin<'a>> {
+        self.admin1
+            .get(format!("{}.{}", country_code, admin1_code).as_str())
+    }
+
+    pub fn admin2(&self, city: &GeonamesCity) -> Option<&Admin<'a>> {
+        self.admin2.get(
+            format!(
+                "{}.{}.{}",
+                city.country_code, city.admin1_code, city.admin2_code
+            )
+            .as_str(),
+        )
+    }
+
+    /// The ISO 3166-2 code of the city's state or province, e.g. "AU-WA".
+    pub fn iso_code(&self, city: &GeonamesCity) -> Option<&'a str> {
+        self.iso_codes
+            .get(format!("{}.{}", city.country_code, city.admin1_code).as_str())
+            .cloned()
+    }
+}
+
+/// A city with a given name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NameMatch<'a> {
+    pub country_code: &'a str,
+    pub geonameid: u32,
+    pub admin1_code: &'a str,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub population: u64,
+}
+
+/// Cities by each of their names, most populous first.
+#[derive(Debug, Default)]
+pub struct NameIndex<'a> {
+    names: HashMap<&'a str, Vec<NameMatch<'a>>>,
+}
+
+impl<'a> NameIndex<'a> {
+    pub fn new(cities: &[GeonamesCity<'a>]) -> NameIndex<'a> {
+        let mut names: HashMap<&str, Vec<NameMatch>> = HashMap::new();
+        for city in cities {
+            let name_match = NameMatch {
+                country_code: city.country_code,
+                geonameid: city.geonameid,
+                admin1_code: city.admin1_code,
+                latitude: city.latitude,
+                longitude: city.longitude,
+                population: city.population,
+            };
+            for name in city.names() {
+                let matches = names.entry(name).or_default();
+                // A city can list the same name more than once.
+                if !matches.contains(&name_match) {
+                    matches.push(name_match);
+                }
+            }
+        }
+
+        // Ties in population go to the lower geonameid, so the order doesn't
+        // depend on the order of the file.
+        for matches in names.values_mut() {
+            matches.sort_by(|a, b| {
+                b.population
+                    .cmp(&a.population)
+                    .then(a.geonameid.cmp(&b.geonameid))
+            });
+        }
+
+        NameIndex { names }
+    }
+
+    /// The cities with a name, most populous first.
+    pub fn matches(&self, name: &str) -> &[NameMatch<'a>] {
+        self.names.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The most populous city with a name.
+    pub fn best(&self, name: &str) -> Option<&NameMatch<'a>> {
+        self.matches(name).first()
+    }
+
+    /// Whether cities in more than one country have a name.
+    pub fn is_ambiguous(&self, name: &str) -> bool {
+        let matches = self.matches(name);
+        matches
+            .iter()
+            .any(|name_match| name_match.country_code != matches[0].country_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CITIES: &str = "\
+2063523\tPerth\tPerth\tPerth City,Perth\t-31.95\t115.86\tP\tPPLA\tAU\t\t08\t57080\t\t\t1896548\t\t20\tAustralia/Perth\t2020-01-01
+2640034\tPerth\tPerth\t\t56.39\t-3.43\tP\tPPL\tGB\t\tSCT\t\t\t\t47180\t\t20\tEurope/London\t2020-01-01
+6167865\tPerth\tPerth\t\t44.9\t-76.25\tP\tPPL\tCA\t\tON\t\t\t\t47180\t\t20\tAmerica/Toronto\t2020-01-01
+3143244\tOslo\tOslo\tChristiania\t59.91\t10.75\tP\tPPLC\tNO\t\t12\t\t\t\t580000\t\t20\tEurope/Oslo\t2020-01-01
+";
+
+    #[test]
+    fn skips_malformed_cities() {
+        let records = parse_cities(
+            "2063523\tPerth\n\
+             x\tPerth\tPerth\t\t56.39\t-3.43\tP\tPPL\tGB\t\tSCT\t\t\t\t47180\t\t20\tEurope/London\t2020-01-01\n",
+        );
+        assert!(records.rows.is_empty());
+        let reasons: Vec<(usize, &str)> = records
+            .malformed
+            .iter()
+            .map(|row| (row.line, row.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (1, "expected at least 19 columns, got 2"),
+                (2, "geonameid \"x\" is not valid"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_every_column() {
+        let cities = parse_cities(CITIES).rows;
+        let perth = &cities[0];
+        assert_eq!(perth.feature_code, "PPLA");
+        assert_eq!(perth.alternate_names, vec!["Perth City", "Perth"]);
+        assert!(perth.cc2.is_empty());
+        assert_eq!(perth.admin2_code, "57080");
+        assert_eq!(perth.elevation, None);
+        assert_eq!(perth.dem, Some(20));
+        assert_eq!(perth.elevation_or_dem(), Some(20));
+        assert_eq!(perth.modification_date, "2020-01-01");
+        assert_eq!(by_id(&cities)[&3143244].name, "Oslo");
+    }
+
+    #[test]
+    fn filters_cities() {
+        let cities = parse_cities(CITIES).rows;
+        let kept = |filter: &Filter| -> Vec<u32> {
+            cities
+                .iter()
+                .filter(|city| filter.matches(city))
+                .map(|city| city.geonameid)
+                .collect()
+        };
+
+        assert_eq!(kept(&Filter::default()).len(), 4);
+        let filter = Filter {
+            min_population: 500000,
+            ..Default::default()
+        };
+        assert_eq!(kept(&filter), vec![2063523, 3143244]);
+        let filter = Filter {
+            feature_codes: vec!["PPLC".to_string(), "PPLA".to_string()],
+            min_population: 1000000,
+        };
+        assert_eq!(kept(&filter), vec![2063523]);
+    }
+
+    #[test]
+    fn streams_cities() {
+        let contents = format!("{}2063523\tPerth\r\n", CITIES);
+        let mut names = Vec::new();
+        let mut malformed = Vec::new();
+        stream_cities(contents.as_bytes(), |city| match city {
+            Ok(city) => names.push(city.name.to_string()),
+            Err(row) => malformed.push(row.line),
+        })
+        .unwrap();
+
+        assert_eq!(names, vec!["Perth", "Perth", "Perth", "Oslo"]);
+        assert_eq!(malformed, vec![5]);
+    }
+
+    #[test]
+    fn indexes_every_name_most_populous_first() {
+        let cities = parse_cities(CITIES).rows;
+        let index = NameIndex::new(&cities);
+
+        let countries: Vec<&str> = index
+            .matches("Perth")
+            .iter()
+            .map(|name_match| name_match.country_code)
+            .collect();
+        // GB and CA have the same population; GB has the lower geonameid.
+        assert_eq!(countries, vec!["AU", "GB", "CA"]);
+        assert!(index.is_ambiguous("Perth"));
+
+        assert_eq!(index.best("Christiania").unwrap().geonameid, 3143244);
+        assert!(!index.is_ambiguous("Oslo"));
+        assert_eq!(index.best("Atlantis"), None);
+    }
+
+    #[test]
+    fn skips_comments_in_country_info() {
+        let records = parse_country_info(
+            "#ISO\tISO3\tISO-Numeric\tfips\tCountry\tCapital\tArea\tPopulation\tContinent\n\
+             NO\tNOR\t578\tNO\tNorway\tOslo\t324220\t5314336\tEU\n\
+             XX\tXXX\n",
+        );
+        assert_eq!(records.malformed.len(), 1);
+        assert_eq!(records.malformed[0].line, 3);
+        let country_info = records.rows;
+        assert_eq!(country_info.len(), 1);
+        assert_eq!(country_info["NO"].continent, "EU");
+        assert_eq!(country_info["NO"].iso3, "NOR");
+        assert_eq!(country_info["NO"].iso_numeric, "578");
+    }
+
+    #[test]
+    fn finds_subdivisions() {
+        let cities = parse_cities(CITIES).rows;
+        let subdivisions = Subdivisions {
+            admin1: parse_admin_codes(
+                "AU.08\tWestern Australia\tWestern Australia\t2058645\n\
+                 NO.12\tOslo\tOslo\t3143242\n",
+            )
+            .rows,
+            admin2: parse_admin_codes("AU.08.57080\tPerth\tPerth\t7839593\n").rows,
+            iso_codes: parse_subdivision_codes("# geonames\tISO 3166-2\nAU.08\tAU-WA\nNO.12\t\n")
+                .rows,
+        };
+
+        let perth = &cities[0];
+        assert_eq!(
+            subdivisions.admin1("AU", "08").unwrap().name,
+            "Western Australia"
+        );
+        assert_eq!(subdivisions.admin2(perth).unwrap().name, "Perth");
+        assert_eq!(subdivisions.iso_code(perth), Some("AU-WA"));
+
+        let oslo = &cities[3];
+        assert_eq!(subdivisions.admin1("NO", "12").unwrap().geonameid, 3143242);
+        assert_eq!(subdivisions.admin2(oslo), None);
+        assert_eq!(subdivisions.iso_code(oslo), None);
+    }
+}
@@ -0,0 +1,102 @@
+//! Reading the rows of tab separated files, keeping the line numbers of rows
+//! that can't be read.
+
+use std::iter::FromIterator;
+use std::path::Path;
+
+/// A row that couldn't be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Malformed {
+    /// From 1, counting comment and blank lines.
+    pub line: usize,
+    pub reason: String,
+}
+
+/// The rows of a file that could be read, and the lines that couldn't.
+#[derive(Debug)]
+pub struct Records<C> {
+    pub rows: C,
+    pub malformed: Vec<Malformed>,
+}
+
+impl<C> Records<C> {
+    /// Logs the malformed lines of the file and returns the rows.
+    pub fn report(self, source: &Path) -> C {
+        for malformed in &self.malformed {
+            println!(
+                "Parse -> Malformed: {}:{}: {}",
+                source.display(),
+                malformed.line,
+                malformed.reason
+            );
+        }
+        self.rows
+    }
+}
+
+/// Reads tab separated rows with at least `columns` columns. Blank lines and
+/// comment lines, which start with `#`, are skipped.
+///
+/// Fields are not quoted. Geonames names can hold a `"`, so a quote is just
+/// part of the field.
+pub fn read_tsv<'a, T, C, F>(contents: &'a str, columns: usize, parse: F) -> Records<C>
+where
+    C: FromIterator<T>,
+    F: Fn(&[&'a str]) -> Result<T, String>,
+{
+    let mut malformed = Vec::new();
+    let rows = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|(index, line)| {
+            let cols: Vec<&str> = line.split('\t').collect();
+            let row = if cols.len() < columns {
+                Err(format!(
+                    "expected at least {} columns, got {}",
+                    columns,
+                    cols.len()
+                ))
+            } else {
+                parse(&cols)
+            };
+            row.map_err(|reason| {
+                malformed.push(Malformed {
+                    line: index + 1,
+                    reason,
+                })
+            })
+            .ok()
+        })
+        .collect();
+
+    Records { rows, malformed }
+}
+
+/// Parses a field, or says which field it was and why it isn't one.
+pub fn parse_field<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{} {:?} is not valid", name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_malformed_lines() {
+        let records: Records<Vec<(u32, String)>> = read_tsv(
+            "# id\tname\n1\tPerth \"WA\"\n\n2\n x\tOslo\n3\tOslo\n",
+            2,
+            |cols| Ok((parse_field("id", cols[0])?, cols[1].to_string())),
+        );
+        assert_eq!(
+            records.rows,
+            vec![(1, "Perth \"WA\"".to_string()), (3, "Oslo".to_string())]
+        );
+        let lines: Vec<usize> = records.malformed.iter().map(|row| row.line).collect();
+        assert_eq!(lines, vec![4, 5]);
+    }
+}
@@ -1,224 +1,457 @@
-extern crate regex;
-extern crate serde;
+extern crate city_climes_core;
+extern crate geonames;
 #[macro_use]
 extern crate serde_json;
-#[macro_use]
-extern crate serde_derive;
-#[macro_use]
-extern crate lazy_static;
 
-use regex::Regex;
+use city_climes_core::disambiguate::{
+    distance_km, Decision, Disambiguator, Signals, LOW_CONFIDENCE,
+};
+use city_climes_core::records::{read_csv, Records};
+use city_climes_core::{CountrySource, Location};
+use geonames::{
+    by_id, parse_admin_codes, parse_cities, parse_country_info, parse_subdivision_codes,
+    CountryInfo, GeonamesCity, NameIndex, Subdivisions,
+};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
-
-lazy_static! {
-    static ref COUNTRY: Regex = Regex::new(r"(?i)country").unwrap();
-}
+use std::path::PathBuf;
 
 pub struct Config {
-    pub json_file: String,
-    pub cities_file: String,
-    pub continents_file: String,
-    pub isos_file: String,
-    pub output_directory: String,
+    pub json_file: PathBuf,
+    pub cities_file: PathBuf,
+    pub continents_file: PathBuf,
+    pub isos_file: PathBuf,
+    /// Geonames `admin1CodesASCII.txt` file, for states and provinces.
+    pub admin1_file: Option<PathBuf>,
+    /// Geonames `admin2Codes.txt` file, for counties and districts.
+    pub admin2_file: Option<PathBuf>,
+    /// ISO 3166-2 codes by geonames admin1 code (see
+    /// `geonames::parse_subdivision_codes`).
+    pub subdivisions_file: Option<PathBuf>,
+    pub output_directory: PathBuf,
+    /// Flags locations whose page coordinates are further than this from
+    /// their geonames city.
+    pub max_distance_km: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Location {
-    ID: u32,
-    WikipediaURL: String,
-    LocationName: String,
-    AverageHighC: Vec<f64>,
-    AverageLowC: Vec<f64>,
-    AverageHighF: Vec<f64>,
-    AverageLowF: Vec<f64>,
-    SunshineHours: Option<Vec<f64>>,
-    Country: Option<String>,
-    ISOCode: Option<String>,
-    Continent: Option<String>,
-}
-
-impl Config {
-    pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
-        args.next();
-
-        let json_file = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a json file path."),
-        };
-
-        let cities_file = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a cities file path."),
-        };
-
-        let continents_file = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a continents file path."),
-        };
-
-        let isos_file = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a iso codes file path."),
-        };
+pub const DEFAULT_MAX_DISTANCE_KM: f64 = 100.0;
 
-        let output_directory = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a output directory path."),
-        };
-
-        Ok(Config {
-            json_file,
-            cities_file,
-            continents_file,
-            isos_file,
-            output_directory,
-        })
-    }
-}
-
-pub fn run(config: Config) -> Result<(), Box<Error>> {
-    let json_file = fs::read_to_string(config.json_file).expect("Unable to read json file");
-    let cities_file = fs::read_to_string(config.cities_file).expect("Unable to read cities file");
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let json_file = fs::read_to_string(&config.json_file).expect("Unable to read json file");
+    let cities_file = fs::read_to_string(&config.cities_file).expect("Unable to read cities file");
     let continents_file =
-        fs::read_to_string(config.continents_file).expect("Unable to read continents file");
-    let isos_file = fs::read_to_string(config.isos_file).expect("Unable to read iso file");
-
-    let out_file_path = format!("{}CityClimesCountriesISO.json", config.output_directory);
+        fs::read_to_string(&config.continents_file).expect("Unable to read continents file");
+    let isos_file = fs::read_to_string(&config.isos_file).expect("Unable to read iso file");
+    let admin1_file = read_optional(&config.admin1_file).expect("Unable to read admin1 file");
+    let admin2_file = read_optional(&config.admin2_file).expect("Unable to read admin2 file");
+    let subdivisions_file =
+        read_optional(&config.subdivisions_file).expect("Unable to read subdivisions file");
+
+    let out_file_path = config.output_directory.join("CityClimesCountriesISO.json");
     let mut out_file = File::create(out_file_path).expect("Could not create file");
+    let ambiguous_file_path = config.output_directory.join("AmbiguousLocations.tsv");
+    let mut ambiguous_file = File::create(ambiguous_file_path).expect("Could not create file");
+    let low_confidence_file_path = config.output_directory.join("LowConfidenceLocations.tsv");
+    let mut low_confidence_file =
+        File::create(low_confidence_file_path).expect("Could not create file");
+    let mismatch_file_path = config.output_directory.join("CoordinateMismatches.tsv");
+    let mut mismatch_file = File::create(mismatch_file_path).expect("Could not create file");
 
     let locations: Vec<Location> = serde_json::from_str(&json_file)?;
-    let cities: HashMap<&str, Vec<&str>> = cities_hashmap(&cities_file);
-    let continents: HashMap<&str, &str> = continents_hashmap(&continents_file);
-    let isos: HashMap<&str, (&str, &str)> = isos_hashmap(&isos_file);
+    let cities = parse_cities(&cities_file).report(&config.cities_file);
+    let names = NameIndex::new(&cities);
+    let cities_by_id = by_id(&cities);
+    let continents = parse_continents(&continents_file).report(&config.continents_file);
+    let isos = parse_country_info(&isos_file).report(&config.isos_file);
+    let subdivisions = Subdivisions {
+        admin1: report_optional(parse_admin_codes(&admin1_file), &config.admin1_file),
+        admin2: report_optional(parse_admin_codes(&admin2_file), &config.admin2_file),
+        iso_codes: report_optional(
+            parse_subdivision_codes(&subdivisions_file),
+            &config.subdivisions_file,
+        ),
+    };
+
+    let disambiguator = Disambiguator::new(&isos).with_subdivisions(&subdivisions);
 
-    // println!("{:?}", &cities.get(&"AX"));
     // let new_locations: Vec<Location> = locations[375..425]
-    let new_locations: Vec<Location> = locations
+    let updates: Vec<(Location, Option<Decision>, Option<f64>)> = locations
         .iter()
-        .map(|location| update_location(location.clone(), &cities, &continents, &isos))
+        .map(|location| {
+            let decision = decide(location, &names, &disambiguator);
+            let city = decision
+                .as_ref()
+                .and_then(|decision| cities_by_id.get(&decision.city.geonameid));
+            let distance = city.and_then(|city| page_distance_km(location, city));
+            let location = update_location(
+                location.clone(),
+                city.cloned(),
+                &continents,
+                &isos,
+                &subdivisions,
+            );
+            (location, decision, distance)
+        })
         .collect();
+    let new_locations: Vec<&Location> = updates.iter().map(|(location, _, _)| location).collect();
     // println!("{:?}", new_locations);
 
-    write!(out_file, "{}", json!(new_locations).to_string()).expect("Unable to write to file.");
+    write!(out_file, "{}", json!(new_locations)).expect("Unable to write to file.");
+
+    // Names shared by cities in more than one country, and the decisions
+    // that are worth checking by hand.
+    let header = "LocationName\tISOCode\tConfidence\tReasons\tCandidates\r\n";
+    write!(ambiguous_file, "{}", header)?;
+    write!(low_confidence_file, "{}", header)?;
+    write!(
+        mismatch_file,
+        "LocationName\tISOCode\tGeonameID\tDistanceKm\r\n"
+    )?;
+    for (location, decision, distance) in &updates {
+        let candidates: Vec<String> = names
+            .matches(&location.LocationName)
+            .iter()
+            .map(|city| {
+                format!(
+                    "{}:{}:{}",
+                    city.country_code, city.geonameid, city.population
+                )
+            })
+            .collect();
+        let (confidence, reasons) = match decision {
+            Some(decision) => (decision.confidence, decision.reasons.join("; ")),
+            None => (0.0, String::new()),
+        };
+        let row = format!(
+            "{}\t{}\t{:.2}\t{}\t{}\r\n",
+            location.LocationName,
+            location.ISOCode.as_deref().unwrap_or_default(),
+            confidence,
+            reasons,
+            candidates.join(",")
+        );
+
+        if names.is_ambiguous(&location.LocationName) {
+            println!(
+                "Ambiguous -> {:?}: {}",
+                &location.LocationName,
+                candidates.join(", ")
+            );
+            write!(ambiguous_file, "{}", row)?;
+        }
+        if decision.is_some() && confidence < LOW_CONFIDENCE {
+            println!(
+                "Low Confidence -> {:?}: {:.2}",
+                &location.LocationName, confidence
+            );
+            write!(low_confidence_file, "{}", row)?;
+        }
+        // The page's coordinates disagree with the city it was matched to.
+        if let Some(distance) = distance.filter(|&distance| distance > config.max_distance_km) {
+            println!(
+                "Coordinate Mismatch -> {:?}: {:.0} km",
+                &location.LocationName, distance
+            );
+            write!(
+                mismatch_file,
+                "{}\t{}\t{}\t{:.1}\r\n",
+                location.LocationName,
+                location.ISOCode.as_deref().unwrap_or_default(),
+                location.GeonameID.unwrap_or_default(),
+                distance
+            )?;
+        }
+    }
 
     Ok(())
 }
 
-fn cities_hashmap(cities: &str) -> HashMap<&str, Vec<&str>> {
-    let mut map: HashMap<&str, Vec<&str>> = HashMap::new();
-
-    for line in cities.lines() {
-        let cols: Vec<&str> = line.split('\t').collect();
-        let iso: &str = cols[8].trim();
-        let name: &str = cols[1].trim();
-        let ascii_name: &str = cols[2];
-        let mut alt_names: Vec<&str> = cols[3].split(',').map(|n| n.trim()).collect();
-
-        alt_names.push(name);
-        alt_names.push(ascii_name);
+/// Continent names by code, e.g. "OC" -> "Oceania", from a CSV file.
+fn parse_continents(contents: &str) -> Records<HashMap<String, String>> {
+    read_csv(contents, 2, |cols| {
+        Ok((cols[0].to_string(), cols[1].to_string()))
+    })
+}
 
-        if map.contains_key(iso) {
-            if let Some(vals) = map.get_mut(iso) {
-                vals.append(&mut alt_names);
-            }
-        } else {
-            map.insert(iso, alt_names);
-        }
+/// An optional file's contents, or nothing if there is no file.
+fn read_optional(path: &Option<PathBuf>) -> std::io::Result<String> {
+    match path {
+        Some(path) => fs::read_to_string(path),
+        None => Ok(String::new()),
     }
-
-    map
 }
 
-fn continents_hashmap(continents: &str) -> HashMap<&str, &str> {
-    let mut map: HashMap<&str, &str> = HashMap::new();
-
-    for line in continents.lines() {
-        let cols: Vec<&str> = line.split(',').collect();
-        let iso: &str = cols[0].trim();
-        let continent: &str = cols[1].trim();
-
-        map.insert(iso, continent);
+fn report_optional<C>(records: Records<C>, path: &Option<PathBuf>) -> C {
+    match path {
+        Some(path) => records.report(path),
+        None => records.rows,
     }
-
-    map
 }
 
-fn isos_hashmap(isos: &str) -> HashMap<&str, (&str, &str)> {
-    let mut map: HashMap<&str, (&str, &str)> = HashMap::new();
-
-    for line in isos.lines() {
-        let cols: Vec<&str> = line.split('\t').collect();
-        let iso: &str = cols[0].trim();
-        let country: &str = cols[4].trim();
-        let continent: &str = cols[8].trim();
+/// "Perth, Western Australia" is listed as "Perth".
+fn city_name(location_name: &str) -> &str {
+    location_name.split(',').next().unwrap_or_default().trim()
+}
 
-        map.insert(iso, (country, continent));
-    }
+fn decide<'a>(
+    location: &Location,
+    names: &NameIndex<'a>,
+    disambiguator: &Disambiguator<'a>,
+) -> Option<Decision<'a>> {
+    let signals = Signals {
+        // Only a country from the infobox is a separate signal; one from
+        // geonames came from the name alone.
+        infobox_country: match location.CountrySource {
+            Some(CountrySource::Infobox) => location.Country.as_deref(),
+            _ => None,
+        },
+        // Until now a location's coordinates are the page's.
+        coordinates: location.Latitude.zip(location.Longitude),
+        ..Signals::from_title(&location.LocationName)
+    };
+    disambiguator.decide(names.matches(city_name(&location.LocationName)), &signals)
+}
 
-    map
+/// How far the location's page coordinates are from the city, if the page
+/// had any.
+fn page_distance_km(location: &Location, city: &GeonamesCity) -> Option<f64> {
+    location
+        .Latitude
+        .zip(location.Longitude)
+        .map(|page| distance_km(page, (city.latitude, city.longitude)))
 }
 
 fn update_location(
     location: Location,
-    cities: &HashMap<&str, Vec<&str>>,
-    continents: &HashMap<&str, &str>,
-    isos: &HashMap<&str, (&str, &str)>,
+    city: Option<&GeonamesCity>,
+    continents: &HashMap<String, String>,
+    isos: &HashMap<&str, CountryInfo>,
+    subdivisions: &Subdivisions,
 ) -> Location {
-    let location_name = location
-        .LocationName
-        .split(',')
-        .collect::<Vec<&str>>()
-        .first()
-        .unwrap()
-        .trim()
-        .clone();
-
-    let mut iso_code: Option<&str> = None;
-    for (key, vals) in cities.iter() {
-        if vals.contains(&location_name) {
-            iso_code = Some(key);
-        }
-    }
-
-    match iso_code {
-        Some(code) => {
-            if let Some((country, continent_iso_code)) = isos.get(code) {
-                let continent = continents.get(continent_iso_code);
+    let location_name = city_name(&location.LocationName).to_string();
+
+    match city {
+        Some(city) => {
+            let location = Location {
+                LocationName: location_name,
+                ISOCode: Some(city.country_code.to_string()),
+                GeonameID: Some(city.geonameid),
+                Latitude: Some(city.latitude),
+                Longitude: Some(city.longitude),
+                Population: Some(city.population),
+                Elevation: city.elevation_or_dem(),
+                Timezone: Some(city.timezone.to_string()).filter(|tz| !tz.is_empty()),
+                Admin1: subdivisions
+                    .admin1(city.country_code, city.admin1_code)
+                    .map(|admin1| admin1.name.to_string()),
+                Admin1ISOCode: subdivisions.iso_code(city).map(|code| code.to_string()),
+                Admin2: subdivisions
+                    .admin2(city)
+                    .map(|admin2| admin2.name.to_string()),
+                ..location
+            };
+
+            if let Some(country_info) = isos.get(city.country_code) {
+                let continent = continents.get(country_info.continent);
 
                 Location {
-                    LocationName: location_name.to_string(),
-                    Country: Some(country.to_string()),
-                    ISOCode: Some(code.to_string()),
+                    Country: Some(country_info.country.to_string()),
+                    CountrySource: Some(CountrySource::Geonames),
+                    ISOAlpha3: Some(country_info.iso3.to_string()),
+                    ISONumeric: Some(country_info.iso_numeric.to_string()),
                     Continent: continent.map(|c| c.to_string()),
                     ..location
                 }
             } else {
+                let (country, country_source) = unresolved_country(&location);
                 Location {
-                    LocationName: location_name.to_string(),
-                    Country: None,
-                    ISOCode: Some(code.to_string()),
+                    Country: country,
+                    CountrySource: country_source,
+                    ISOAlpha3: None,
+                    ISONumeric: None,
                     Continent: None,
                     ..location
                 }
             }
         }
-        None => Location {
-            LocationName: location_name.to_string(),
-            Country: None,
-            ISOCode: None,
-            Continent: None,
-            ..location
-        },
+        None => {
+            let (country, country_source) = unresolved_country(&location);
+            Location {
+                LocationName: location_name,
+                Country: country,
+                CountrySource: country_source,
+                ISOCode: None,
+                ISOAlpha3: None,
+                ISONumeric: None,
+                Admin1: None,
+                Admin1ISOCode: None,
+                Admin2: None,
+                Continent: None,
+                GeonameID: None,
+                Population: None,
+                Elevation: None,
+                Timezone: None,
+                ..location
+            }
+        }
     }
 }
 
+/// The country of a location without a geonames country: the one scraped
+/// from its page's infobox, if `add_countries` found one.
+fn unresolved_country(location: &Location) -> (Option<String>, Option<CountrySource>) {
+    match location.CountrySource {
+        Some(CountrySource::Infobox) => (location.Country.clone(), location.CountrySource),
+        _ => (None, Some(CountrySource::Unresolved)),
+    }
+}
+
+#[cfg(test)]
 mod test {
-    // use super::*;
+    use super::*;
 
     #[test]
     fn case_senstive() {}
+
+    #[test]
+    fn disambiguates_cities() {
+        let cities = parse_cities(
+            "2640034\tPerth\tPerth\t\t56.39\t-3.43\tP\tPPL\tGB\t\tSCT\t\t\t\t47180\t\t20\tEurope/London\t2020-01-01\n\
+             2063523\tPerth\tPerth\t\t-31.95\t115.86\tP\tPPLA\tAU\t\t08\t\t\t\t1896548\t\t20\tAustralia/Perth\t2020-01-01\n",
+        );
+        let cities = cities.rows;
+        let names = NameIndex::new(&cities);
+        let isos = parse_country_info(
+            "AU\tAUS\t036\tAS\tAustralia\tCanberra\t7686850\t24992369\tOC\n\
+             GB\tGBR\t826\tUK\tUnited Kingdom\tLondon\t244820\t66488991\tEU\n",
+        )
+        .rows;
+        let continents = parse_continents("OC,Oceania\nEU,Europe\n").rows;
+        let subdivisions = Subdivisions {
+            admin1: parse_admin_codes(
+                "AU.08\tWestern Australia\tWestern Australia\t2058645\n\
+                 GB.SCT\tScotland\tScotland\t2638360\n",
+            )
+            .rows,
+            iso_codes: parse_subdivision_codes("AU.08\tAU-WA\n").rows,
+            ..Default::default()
+        };
+        let location: Location = serde_json::from_value(json!({
+            "ID": 1,
+            "WikipediaURL": "https://en.wikipedia.org/wiki/Climate_of_Perth",
+            "LocationName": "Perth, Western Australia",
+            "AverageHighC": [],
+            "AverageLowC": [],
+            "AverageHighF": [],
+            "AverageLowF": [],
+            "SunshineHours": null,
+            "Country": null,
+            "ISOCode": null,
+            "Continent": null
+        }))
+        .unwrap();
+
+        let disambiguator = Disambiguator::new(&isos).with_subdivisions(&subdivisions);
+        let update = |location: &Location| {
+            let decision = decide(location, &names, &disambiguator).unwrap();
+            let city = cities
+                .iter()
+                .find(|city| city.geonameid == decision.city.geonameid);
+            (
+                update_location(location.clone(), city, &continents, &isos, &subdivisions),
+                decision,
+            )
+        };
+
+        let (updated, decision) = update(&location);
+        assert_eq!(updated.LocationName, "Perth");
+        assert_eq!(updated.ISOCode, Some("AU".to_string()));
+        assert_eq!(updated.Continent, Some("Oceania".to_string()));
+        assert_eq!(updated.GeonameID, Some(2063523));
+        assert_eq!(updated.Latitude, Some(-31.95));
+        assert_eq!(updated.Population, Some(1896548));
+        assert_eq!(updated.Elevation, Some(20));
+        assert_eq!(updated.Timezone, Some("Australia/Perth".to_string()));
+        assert_eq!(updated.ISOAlpha3, Some("AUS".to_string()));
+        assert_eq!(updated.ISONumeric, Some("036".to_string()));
+        assert_eq!(updated.Admin1, Some("Western Australia".to_string()));
+        assert_eq!(updated.Admin1ISOCode, Some("AU-WA".to_string()));
+        assert_eq!(updated.Admin2, None);
+        assert!(decision.confidence > LOW_CONFIDENCE);
+        assert!(names.is_ambiguous("Perth"));
+
+        // The infobox outweighs population.
+        let location = Location {
+            LocationName: "Perth".to_string(),
+            Country: Some("United Kingdom".to_string()),
+            CountrySource: Some(CountrySource::Infobox),
+            ..location
+        };
+        let (updated, _) = update(&location);
+        assert_eq!(updated.ISOCode, Some("GB".to_string()));
+        assert_eq!(updated.Admin1, Some("Scotland".to_string()));
+        assert_eq!(updated.Admin1ISOCode, None);
+
+        // So does a title naming the state.
+        let location = Location {
+            LocationName: "Perth, Scotland".to_string(),
+            CountrySource: None,
+            ..location
+        };
+        let (updated, _) = update(&location);
+        assert_eq!(updated.ISOCode, Some("GB".to_string()));
+
+        // Scottish page coordinates are far from the Australian city.
+        let location = Location {
+            Latitude: Some(56.4),
+            Longitude: Some(-3.4),
+            ..location
+        };
+        let australia = cities
+            .iter()
+            .find(|city| city.country_code == "AU")
+            .unwrap();
+        let distance = page_distance_km(&location, australia).unwrap();
+        assert!(distance > DEFAULT_MAX_DISTANCE_KM);
+        assert!(page_distance_km(&location, &cities[0]).unwrap() < 5.0);
+
+        // Without a geonames city, the infobox country is kept.
+        let location = Location {
+            LocationName: "Darwin, Northern Territory".to_string(),
+            Country: Some("Australia".to_string()),
+            CountrySource: Some(CountrySource::Infobox),
+            ..location
+        };
+        let updated = update_location(location.clone(), None, &continents, &isos, &subdivisions);
+        assert_eq!(updated.LocationName, "Darwin");
+        assert_eq!(updated.Country, Some("Australia".to_string()));
+        assert_eq!(updated.CountrySource, Some(CountrySource::Infobox));
+        assert_eq!(updated.ISOCode, None);
+
+        // Nor is it dropped for a city of a country without ISO codes.
+        let city = GeonamesCity {
+            country_code: "XX",
+            ..australia.clone()
+        };
+        let updated = update_location(
+            location.clone(),
+            Some(&city),
+            &continents,
+            &isos,
+            &subdivisions,
+        );
+        assert_eq!(updated.Country, Some("Australia".to_string()));
+        assert_eq!(updated.CountrySource, Some(CountrySource::Infobox));
+
+        let location = Location {
+            CountrySource: Some(CountrySource::Geonames),
+            ..location
+        };
+        let updated = update_location(location, None, &continents, &isos, &subdivisions);
+        assert_eq!(updated.Country, None);
+        assert_eq!(updated.CountrySource, Some(CountrySource::Unresolved));
+    }
 }
@@ -1,18 +0,0 @@
-extern crate iso_add_countries;
-
-use std::env;
-use std::process;
-
-use iso_add_countries::Config;
-
-fn main() {
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
-    });
-
-    if let Err(e) = iso_add_countries::run(config) {
-        eprintln!("Application error: {}", e);
-        process::exit(1);
-    }
-}
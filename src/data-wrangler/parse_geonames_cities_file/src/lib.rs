@@ -1,50 +1,76 @@
-use std::collections::HashSet;
+extern crate geonames;
+
+use geonames::{
+    parse_admin_codes, parse_country_info, stream_cities, Admin, CountryInfo, Filter, GeonamesCity,
+};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::error::Error;
+use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::PathBuf;
 
 pub struct Config {
-    pub filename: String,
-    pub output_directory: String,
+    pub filename: PathBuf,
+    /// Candidate titles not in the Wikipedia index are left out.
+    pub wikipedia_index_file: PathBuf,
+    /// Geonames `countryInfo.txt` file, for "Perth, Australia" titles.
+    pub country_info_file: Option<PathBuf>,
+    /// Geonames `admin1CodesASCII.txt` file, for "Perth, Western Australia"
+    /// titles.
+    pub admin1_file: Option<PathBuf>,
+    pub output_directory: PathBuf,
+    /// Which cities to list, e.g. only those of at least 100,000 people.
+    pub filter: Filter,
 }
 
-impl Config {
-    pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
-        args.next();
-
-        let filename = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a geonames cities file path."),
-        };
-
-        let output_directory = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a output directory path."),
-        };
-
-        Ok(Config {
-            filename,
-            output_directory,
-        })
-    }
-}
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let f = File::open(&config.filename).expect("file not found");
+    let wikipedia_index_file = fs::read_to_string(&config.wikipedia_index_file)
+        .expect("Unable to read wikipedia index file");
+    let country_info_file = read_optional(&config.country_info_file)?;
+    let admin1_file = read_optional(&config.admin1_file)?;
 
-pub fn run(config: Config) -> Result<(), Box<Error>> {
-    let mut f = File::open(config.filename).expect("file not found");
-
-    let mut contents = String::new();
-
-    f.read_to_string(&mut contents)?;
-
-    let city_pages: HashSet<&str> = contents
+    // Index lines are "offset:page id:title", and titles can hold a colon.
+    let index: HashSet<&str> = wikipedia_index_file
         .lines()
-        .map(|line| {
-            let split_line: Vec<&str> = line.split('\t').collect();
-            split_line[1]
-        })
+        .filter_map(|line| line.splitn(3, ':').nth(2))
         .collect();
+    let countries = match config.country_info_file {
+        Some(ref path) => parse_country_info(&country_info_file).report(path),
+        None => HashMap::new(),
+    };
+    let admin1 = match config.admin1_file {
+        Some(ref path) => parse_admin_codes(&admin1_file).report(path),
+        None => HashMap::new(),
+    };
 
-    let full_path = format!("{}{}", &config.output_directory, "pages_to_fetch.txt");
+    // Sorted, so the same cities file always gives the same list.
+    let mut city_pages: BTreeSet<String> = BTreeSet::new();
+    stream_cities(BufReader::new(f), |city| match city {
+        Ok(city) => {
+            if config.filter.matches(&city) {
+                let titles = candidate_titles(&city, &countries, &admin1);
+                let found: Vec<String> = titles
+                    .into_iter()
+                    .filter(|title| index.contains(title.as_str()))
+                    .collect();
+                if found.is_empty() {
+                    println!("Candidates -> Not In Index: {:?}", city.name);
+                }
+                city_pages.extend(found);
+            }
+        }
+        Err(malformed) => println!(
+            "Parse -> Malformed: {}:{}: {}",
+            config.filename.display(),
+            malformed.line,
+            malformed.reason
+        ),
+    })?;
+
+    let full_path = config.output_directory.join("pages_to_fetch.txt");
     let mut f = File::create(full_path).expect("Could not create file");
 
     for page in city_pages {
@@ -54,9 +80,67 @@ pub fn run(config: Config) -> Result<(), Box<Error>> {
     Ok(())
 }
 
+/// An optional file's contents, or nothing if there is no file.
+fn read_optional(path: &Option<PathBuf>) -> std::io::Result<String> {
+    match path {
+        Some(path) => fs::read_to_string(path),
+        None => Ok(String::new()),
+    }
+}
+
+/// The titles a city's climate data could be under. Most cities have no
+/// "Climate of" page, and their weather box is in their own page's climate
+/// section, which can be titled after the state or country when the name is
+/// shared.
+fn candidate_titles(
+    city: &GeonamesCity,
+    countries: &HashMap<&str, CountryInfo>,
+    admin1: &HashMap<&str, Admin>,
+) -> Vec<String> {
+    let mut titles = vec![format!("Climate of {}", city.name), city.name.to_string()];
+    let admin1_key = format!("{}.{}", city.country_code, city.admin1_code);
+    if let Some(admin1) = admin1.get(admin1_key.as_str()) {
+        titles.push(format!("{}, {}", city.name, admin1.name));
+    }
+    if let Some(country) = countries.get(city.country_code) {
+        titles.push(format!("{}, {}", city.name, country.country));
+    }
+    titles
+}
+
+#[cfg(test)]
 mod test {
-    // use super::*;
+    use super::*;
+    use geonames::parse_cities;
 
     #[test]
     fn case_senstive() {}
+
+    #[test]
+    fn generates_candidate_titles() {
+        let cities = parse_cities(
+            "2063523\tPerth\tPerth\t\t-31.95\t115.86\tP\tPPLA\tAU\t\t08\t\t\t\t1896548\t\t20\tAustralia/Perth\t2020-01-01\n\
+             2640034\tPerth\tPerth\t\t56.39\t-3.43\tP\tPPL\tGB\t\tSCT\t\t\t\t47180\t\t20\tEurope/London\t2020-01-01\n",
+        )
+        .rows;
+        let countries =
+            parse_country_info("AU\tAUS\t036\tAS\tAustralia\tCanberra\t7686850\t24992369\tOC\n")
+                .rows;
+        let admin1 =
+            parse_admin_codes("AU.08\tWestern Australia\tWestern Australia\t2058645\n").rows;
+
+        assert_eq!(
+            candidate_titles(&cities[0], &countries, &admin1),
+            vec![
+                "Climate of Perth",
+                "Perth",
+                "Perth, Western Australia",
+                "Perth, Australia"
+            ]
+        );
+        assert_eq!(
+            candidate_titles(&cities[1], &countries, &admin1),
+            vec!["Climate of Perth", "Perth"]
+        );
+    }
 }
@@ -1,44 +1,27 @@
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
 pub struct Config {
-    pub filename: String,
-    pub output_directory: String,
+    pub filename: PathBuf,
+    pub output_directory: PathBuf,
 }
 
-impl Config {
-    pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
-        args.next();
-
-        let filename = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file path."),
-        };
-
-        let output_directory = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a output directory path."),
-        };
-
-        Ok(Config {
-            filename,
-            output_directory,
-        })
-    }
-}
-
-pub fn run(config: Config) -> Result<(), Box<Error>> {
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     let mut f = File::open(config.filename).expect("file not found");
 
     let mut contents = String::new();
     f.read_to_string(&mut contents)?;
 
     let (climate_pages, geography_pages) = pages(&contents);
+    let geography_minus_climate_pages = geography_minus_climate(&climate_pages, &geography_pages);
 
+    // Climate pages first, as they are the better source of climate data.
     let mut pages_to_fetch = Vec::new();
     pages_to_fetch.append(&mut climate_pages.clone());
-    pages_to_fetch.append(&mut geography_pages.clone());
+    pages_to_fetch.append(&mut geography_minus_climate_pages.clone());
 
     let fixed_pages_to_fetch = fix_ampersands(&pages_to_fetch);
 
@@ -51,6 +34,11 @@ pub fn run(config: Config) -> Result<(), Box<Error>> {
         "geography_pages.txt",
         geography_pages,
     )?;
+    write_file(
+        &config.output_directory,
+        "geography_minus_climate_pages.txt",
+        geography_minus_climate_pages,
+    )?;
     write_file(
         &config.output_directory,
         "pages_to_fetch.txt",
@@ -60,7 +48,7 @@ pub fn run(config: Config) -> Result<(), Box<Error>> {
     Ok(())
 }
 
-fn pages<'a>(contents: &'a str) -> (Vec<&'a str>, Vec<&'a str>) {
+fn pages(contents: &str) -> (Vec<&str>, Vec<&str>) {
     let mut climate_pages: Vec<&str> = Vec::new();
     let mut geography_pages: Vec<&str> = Vec::new();
 
@@ -81,19 +69,80 @@ fn pages<'a>(contents: &'a str) -> (Vec<&'a str>, Vec<&'a str>) {
     (climate_pages, geography_pages)
 }
 
-fn fix_ampersands<'a>(pages_to_fetch: &Vec<&'a str>) -> Vec<String> {
+fn geography_minus_climate<'a>(
+    climate_pages: &Vec<&str>,
+    geography_pages: &Vec<&'a str>,
+) -> Vec<&'a str> {
+    let mut climate_locations: HashMap<String, HashSet<Option<String>>> = HashMap::new();
+    for page in climate_pages {
+        let (location, disambiguator) = normalize_location(page);
+        climate_locations
+            .entry(location)
+            .or_default()
+            .insert(disambiguator);
+    }
+
+    geography_pages
+        .iter()
+        .filter(|page| {
+            let (location, disambiguator) = normalize_location(page);
+            !climate_locations
+                .get(&location)
+                .is_some_and(|disambiguators| {
+                    disambiguator.is_none()
+                        || disambiguators.contains(&None)
+                        || disambiguators.contains(&disambiguator)
+                })
+        })
+        .cloned()
+        .collect()
+}
+
+/// Reduces a "Climate of ..." or "Geography of ..." page name to a location
+/// that can be compared across both, e.g. "Geography of the Gambia (country)"
+/// and "Climate of The Gambia" both become "gambia", and its disambiguator,
+/// e.g. "country". Pages of the same location only match when at most one of
+/// them has a disambiguator or both have the same one.
+fn normalize_location(page: &str) -> (String, Option<String>) {
+    let location = page
+        .trim_start_matches("Climate of ")
+        .trim_start_matches("Geography of ");
+
+    let (location, disambiguator) = match location.find(" (") {
+        Some(index) if location.ends_with(')') => (
+            &location[..index],
+            Some(
+                location[index + 2..location.len() - 1]
+                    .trim()
+                    .to_lowercase(),
+            ),
+        ),
+        _ => (location, None),
+    };
+
+    let location = location.trim().to_lowercase();
+
+    let location = match location.strip_prefix("the ") {
+        Some(location) => location.to_string(),
+        None => location,
+    };
+
+    (location, disambiguator)
+}
+
+fn fix_ampersands(pages_to_fetch: &Vec<&str>) -> Vec<String> {
     pages_to_fetch
-        .into_iter()
+        .iter()
         .map(|page| page.replace(" &amp; ", " & "))
         .collect()
 }
 
 fn write_file(
-    output_directory: &str,
+    output_directory: &Path,
     filename: &str,
     page_list: Vec<&str>,
-) -> Result<(), Box<Error>> {
-    let full_path = format!("{}{}", output_directory, filename);
+) -> Result<(), Box<dyn Error>> {
+    let full_path = output_directory.join(filename);
     let mut f = File::create(full_path).expect("Could not create file");
 
     for page in page_list {
@@ -103,9 +152,51 @@ fn write_file(
     Ok(())
 }
 
+#[cfg(test)]
 mod test {
-    // use super::*;
+    use super::*;
 
     #[test]
     fn case_senstive() {}
+
+    #[test]
+    fn normalizes_locations() {
+        assert_eq!(
+            normalize_location("Climate of The Gambia"),
+            ("gambia".to_string(), None)
+        );
+        assert_eq!(
+            normalize_location("Geography of the Gambia"),
+            ("gambia".to_string(), None)
+        );
+        assert_eq!(
+            normalize_location("Geography of Georgia (country)"),
+            ("georgia".to_string(), Some("country".to_string()))
+        );
+        assert_eq!(
+            normalize_location("Climate of New York City"),
+            ("new york city".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn removes_geography_pages_with_climate_pages() {
+        let climate_pages = vec![
+            "Climate of The Gambia",
+            "Climate of Georgia (U.S. state)",
+            "Climate of Punjab",
+        ];
+        let geography_pages = vec![
+            "Geography of Georgia (U.S. state)",
+            "Geography of Georgia (country)",
+            "Geography of Peru",
+            "Geography of Punjab (India)",
+            "Geography of the Gambia",
+        ];
+
+        assert_eq!(
+            geography_minus_climate(&climate_pages, &geography_pages),
+            vec!["Geography of Georgia (country)", "Geography of Peru"]
+        );
+    }
 }
@@ -1,19 +1,27 @@
-CREATE TABLE "FetchAndParseResults" (
+CREATE TABLE IF NOT EXISTS "FetchAndParseResults" (
     `ID` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
     `PageName` TEXT NOT NULL,
     `FetchResult` TEXT NOT NULL,
     `ResponseURL` TEXT,
     `StatusCode` INTEGER,
     `ContentLocationURL` TEXT,
+    `PageID` INTEGER,
+    `AliasTitles` TEXT,
     `WikipediaURL` TEXT,
     `LocationName` TEXT,
+    `Latitude` REAL,
+    `Longitude` REAL,
     `TableHTML` TEXT,
+    `Section` TEXT,
     `TemperatureTableType` TEXT,
     `AverageHighC` TEXT,
     `AverageLowC` TEXT,
     `AverageHighF` TEXT,
     `AverageLowF` TEXT,
     `SunshineHours` TEXT,
+    `PercentPossibleSunshine` TEXT,
+    `DaylightHours` TEXT,
+    `SunshineConversion` TEXT,
     `ParseResult` TEXT,
     `DateAddedToDB` NUMERIC NOT NULL DEFAULT CURRENT_TIMESTAMP
 )
\ No newline at end of file
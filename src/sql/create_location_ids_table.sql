@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS "LocationIDs" (
+    `ID` INTEGER NOT NULL PRIMARY KEY,
+    `PageID` INTEGER,
+    `LocationName` TEXT NOT NULL,
+    `Exported` INTEGER NOT NULL DEFAULT 1,
+    `DateAdded` NUMERIC NOT NULL DEFAULT CURRENT_TIMESTAMP
+)
@@ -1,16 +1,21 @@
+-- The best row comes first for each LocationName; sqlite::export keeps only
+-- that one.
 select
 	ID,
+	PageID,
 	WikipediaURL,
 	LocationName,
 	AverageHighC,
     AverageLowC,
     AverageHighF,
     AverageLowF,
-	SunshineHours
+	SunshineHours,
+	Latitude,
+	Longitude
 from FetchAndParseResults
-where AverageHighC is not null
-group by LocationName
+where ParseResult = 'Parsed'
 order by
-	TemperatureTableType desc,
 	LocationName asc,
-	SunshineHours desc
\ No newline at end of file
+	TemperatureTableType desc,
+	SunshineHours desc,
+	ID asc
\ No newline at end of file